@@ -0,0 +1,118 @@
+use bevy::{input::mouse::MouseWheel, prelude::*};
+use bevy_tile_map::prelude::*;
+
+// Controls: W, A, S, D to move the camera and the mouse wheel to zoom.
+
+fn main() {
+    App::new()
+        .add_plugins(DefaultPlugins)
+        .add_plugin(TileMapPlugin)
+        .add_startup_system(setup)
+        .add_system(control_camera)
+        .run();
+}
+
+fn setup(
+    mut commands: Commands,
+    windows: Res<Windows>,
+    asset_server: Res<AssetServer>,
+    mut tile_sheets: ResMut<Assets<TileSheet>>,
+) {
+    let mut tile_sheet = TileSheet::new(
+        vec![asset_server.load("0x72_16x16DungeonTileset.v4.png")],
+        UVec2::new(16, 16),
+    );
+    // The candles in the tile set.
+    let flicker = tile_sheet.add_animation(TileAnimation::from_indices(
+        152..156,
+        0.15,
+        TileAnimationMode::PingPong,
+    ));
+    let burn_down = tile_sheet.add_animation(TileAnimation::new(
+        vec![
+            TileAnimationFrame {
+                idx: 152,
+                duration: 2.0,
+            },
+            TileAnimationFrame {
+                idx: 153,
+                duration: 0.5,
+            },
+            TileAnimationFrame {
+                idx: 154,
+                duration: 0.5,
+            },
+            TileAnimationFrame {
+                idx: 155,
+                duration: 1.0,
+            },
+        ],
+        TileAnimationMode::Loop,
+    ));
+    let tile_sheet = tile_sheets.add(tile_sheet);
+
+    let mut tile_map = TileMap::new(
+        UVec2::new(64, 64),
+        UVec2::new(32, 32),
+        UVec2::new(16, 16),
+        tile_sheet,
+    );
+
+    for x in 0..tile_map.size.x {
+        for y in 0..tile_map.size.y {
+            tile_map[(x, y, 0)] = Tile {
+                entity: None,
+                kind: Some(TileKind::AnimatedSprite {
+                    animation: if (x + y) % 2 == 0 { flicker } else { burn_down },
+                    transform: TileTransform::default(),
+                    mask_color: Color::WHITE,
+                }),
+                pickable: true,
+            };
+        }
+    }
+
+    let window = windows.get_primary().unwrap();
+    commands.spawn_bundle(TileMapBundle {
+        tile_map,
+        transform: TransformBundle {
+            local: Transform::from_translation(Vec3::new(
+                -window.width() / 2.0,
+                -window.height() / 2.0,
+                0.0,
+            )),
+            ..Default::default()
+        },
+        ..Default::default()
+    });
+    commands.spawn_bundle(OrthographicCameraBundle::new_2d());
+}
+
+fn control_camera(
+    input: Res<Input<KeyCode>>,
+    mut mouse_wheel_events: EventReader<MouseWheel>,
+    mut camera: Query<(&mut Transform, &mut OrthographicProjection), With<Camera>>,
+) {
+    for (mut transform, mut projection) in camera.iter_mut() {
+        const SPEED: f32 = 20.0;
+
+        if input.pressed(KeyCode::W) {
+            transform.translation.y += SPEED;
+        }
+        if input.pressed(KeyCode::S) {
+            transform.translation.y -= SPEED;
+        }
+        if input.pressed(KeyCode::A) {
+            transform.translation.x -= SPEED;
+        }
+        if input.pressed(KeyCode::D) {
+            transform.translation.x += SPEED;
+        }
+
+        const MOUSE_SPEED: f32 = 0.1;
+
+        for event in mouse_wheel_events.iter() {
+            projection.scale = (projection.scale - event.y * MOUSE_SPEED).max(0.0001);
+        }
+    }
+}
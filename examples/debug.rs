@@ -0,0 +1,129 @@
+use bevy::{input::mouse::MouseWheel, prelude::*};
+use bevy_tile_map::prelude::*;
+
+// Run with `--features debug`.
+// Controls: Click on tiles to toggle them, their chunk flashes when it gets rebuilt. G toggles the
+// tile grid, B the chunk borders and F the flashing of rebuilt chunks.
+
+fn main() {
+    App::new()
+        .add_plugins(DefaultPlugins)
+        .add_plugin(TileMapPlugin)
+        .add_plugin(TileMapDebugPlugin)
+        .add_startup_system(setup)
+        .add_system(toggle_tiles)
+        .add_system(toggle_overlays)
+        .add_system(control_camera)
+        .run();
+}
+
+fn colored_tile(color: Color) -> Tile {
+    Tile {
+        entity: None,
+        kind: Some(TileKind::Color {
+            color,
+            transform: TileTransform::default(),
+        }),
+        pickable: true,
+    }
+}
+
+fn setup(
+    mut commands: Commands,
+    windows: Res<Windows>,
+    mut tile_sheets: ResMut<Assets<TileSheet>>,
+) {
+    let mut tile_map = TileMap::new(
+        UVec2::new(64, 64),
+        UVec2::new(8, 8),
+        UVec2::new(16, 16),
+        tile_sheets.add(TileSheet::empty()),
+    );
+
+    for x in 0..tile_map.size.x {
+        for y in 0..tile_map.size.y {
+            if (x * 7 + y * 3) % 5 != 0 {
+                tile_map[(x, y, 0)] = colored_tile(Color::DARK_GRAY);
+            }
+        }
+    }
+
+    let window = windows.get_primary().unwrap();
+    commands.spawn_bundle(TileMapBundle {
+        tile_map,
+        transform: TransformBundle {
+            local: Transform::from_translation(Vec3::new(
+                -window.width() / 2.0,
+                -window.height() / 2.0,
+                0.0,
+            )),
+            ..Default::default()
+        },
+        ..Default::default()
+    });
+
+    commands
+        .spawn_bundle(OrthographicCameraBundle::new_2d())
+        .insert(TileMapRayCastSource::default());
+}
+
+fn toggle_tiles(
+    mut tile_map_events: EventReader<TileMapInteractionEvent>,
+    mut tile_maps: Query<&mut TileMap>,
+) {
+    for event in tile_map_events.iter() {
+        if event.kind == TileMapInteractionKind::Clicked(MouseButton::Left) {
+            let mut tile_map = tile_maps.get_mut(event.tile_map).unwrap();
+            let tile = &mut tile_map[event.coord];
+            *tile = match tile.kind {
+                Some(TileKind::Color { color, .. }) if color == Color::ORANGE => {
+                    colored_tile(Color::DARK_GRAY)
+                }
+                _ => colored_tile(Color::ORANGE),
+            };
+        }
+    }
+}
+
+fn toggle_overlays(input: Res<Input<KeyCode>>, mut settings: ResMut<TileMapDebugSettings>) {
+    let defaults = TileMapDebugSettings::default();
+
+    if input.just_pressed(KeyCode::G) {
+        settings.grid = settings.grid.xor(defaults.grid);
+    }
+    if input.just_pressed(KeyCode::B) {
+        settings.chunk_borders = settings.chunk_borders.xor(defaults.chunk_borders);
+    }
+    if input.just_pressed(KeyCode::F) {
+        settings.dirty_flash = settings.dirty_flash.xor(defaults.dirty_flash);
+    }
+}
+
+fn control_camera(
+    input: Res<Input<KeyCode>>,
+    mut mouse_wheel_events: EventReader<MouseWheel>,
+    mut camera: Query<(&mut Transform, &mut OrthographicProjection), With<Camera>>,
+) {
+    for (mut transform, mut projection) in camera.iter_mut() {
+        const SPEED: f32 = 20.0;
+
+        if input.pressed(KeyCode::W) {
+            transform.translation.y += SPEED;
+        }
+        if input.pressed(KeyCode::S) {
+            transform.translation.y -= SPEED;
+        }
+        if input.pressed(KeyCode::A) {
+            transform.translation.x -= SPEED;
+        }
+        if input.pressed(KeyCode::D) {
+            transform.translation.x += SPEED;
+        }
+
+        const MOUSE_SPEED: f32 = 0.1;
+
+        for event in mouse_wheel_events.iter() {
+            projection.scale = (projection.scale - event.y * MOUSE_SPEED).max(0.0001);
+        }
+    }
+}
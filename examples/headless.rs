@@ -0,0 +1,50 @@
+use bevy::prelude::*;
+use bevy_tile_map::prelude::*;
+
+// Runs without a window or a GPU, like a dedicated server would. Only needs the `TileMapCorePlugin`,
+// so the crate can be used with `default-features = false`.
+
+fn main() {
+    let mut app = App::new();
+    app.add_plugins(MinimalPlugins)
+        .add_plugin(TileMapCorePlugin)
+        .add_startup_system(setup);
+
+    for _ in 0..3 {
+        app.update();
+    }
+
+    for colliders in app.world.query::<&TileMapColliders>().iter(&app.world) {
+        println!("The tile map has {} colliders", colliders.rects().count());
+    }
+}
+
+fn setup(mut commands: Commands) {
+    let mut tile_map = TileMap::new(
+        UVec2::new(64, 64),
+        UVec2::new(16, 16),
+        UVec2::new(16, 16),
+        Handle::default(),
+    );
+
+    for x in 0..tile_map.size.x {
+        tile_map[(x, 0, 0)] = Tile {
+            entity: None,
+            kind: Some(TileKind::Color {
+                color: Color::GRAY,
+                transform: TileTransform::default(),
+            }),
+            pickable: false,
+        };
+    }
+
+    commands
+        .spawn_bundle(TileMapBundle {
+            tile_map,
+            ..Default::default()
+        })
+        .insert(TileMapColliderGenerator::any_tile(
+            vec![0],
+            ColliderSpace::Local,
+        ));
+}
@@ -94,12 +94,9 @@ fn print_tile_click_events(
     tile_maps: Query<&TileMap>,
 ) {
     for event in tile_map_events.iter() {
-        match event {
-            TileMapInteractionEvent::Clicked(tile_map_entity, tile_coord) => {
-                let tile_map = tile_maps.get(*tile_map_entity).unwrap();
-                println!("{:?}, {:?}", tile_coord, tile_map[*tile_coord]);
-            }
-            _ => {}
+        if event.kind == TileMapInteractionKind::Clicked(MouseButton::Left) {
+            let tile_map = tile_maps.get(event.tile_map).unwrap();
+            println!("{:?}, {:?}", event.coord, tile_map[event.coord]);
         }
     }
 }
@@ -0,0 +1,144 @@
+use bevy::prelude::*;
+use bevy_tile_map::prelude::*;
+
+// Controls: Arrow Left, Arrow Right to walk, Space to jump. Gray tiles are solid, blue tiles are
+// one way platforms and green tiles are slopes.
+
+const SOLID: Color = Color::GRAY;
+const ONE_WAY: Color = Color::BLUE;
+const SLOPE_UP_RIGHT: Color = Color::GREEN;
+const SLOPE_UP_LEFT: Color = Color::DARK_GREEN;
+
+fn main() {
+    App::new()
+        .add_plugins(DefaultPlugins)
+        .add_plugin(TileMapPlugin)
+        .add_startup_system(setup)
+        .add_system(control_player)
+        .add_system(log_contacts)
+        .run();
+}
+
+fn color_tile(color: Color) -> Tile {
+    Tile {
+        entity: None,
+        kind: Some(TileKind::Color {
+            color,
+            transform: TileTransform::default(),
+        }),
+        pickable: false,
+    }
+}
+
+fn setup(
+    mut commands: Commands,
+    windows: Res<Windows>,
+    mut tile_sheets: ResMut<Assets<TileSheet>>,
+) {
+    let mut tile_map = TileMap::new(
+        UVec2::new(40, 20),
+        UVec2::new(16, 16),
+        UVec2::new(32, 32),
+        tile_sheets.add(TileSheet::empty()),
+    );
+
+    for x in 0..tile_map.size.x {
+        tile_map[(x, 0, 0)] = color_tile(SOLID);
+    }
+    let right_wall = tile_map.size.x - 1;
+    for y in 1..tile_map.size.y {
+        tile_map[(0, y, 0)] = color_tile(SOLID);
+        tile_map[(right_wall, y, 0)] = color_tile(SOLID);
+    }
+    for x in 6..12 {
+        tile_map[(x, 4, 0)] = color_tile(ONE_WAY);
+    }
+    for step in 0..4 {
+        tile_map[(16 + step, 1 + step, 0)] = color_tile(SLOPE_UP_RIGHT);
+        tile_map[(23 - step, 1 + step, 0)] = color_tile(SLOPE_UP_LEFT);
+        for y in 1..1 + step {
+            tile_map[(16 + step, y, 0)] = color_tile(SOLID);
+            tile_map[(23 - step, y, 0)] = color_tile(SOLID);
+        }
+    }
+
+    let window = windows.get_primary().unwrap();
+    let tile_map_entity = commands
+        .spawn_bundle(TileMapBundle {
+            tile_map,
+            transform: TransformBundle {
+                local: Transform::from_translation(Vec3::new(
+                    -window.width() / 2.0,
+                    -window.height() / 2.0,
+                    0.0,
+                )),
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .insert(TileMapCollision::new(0, |tile| match tile.kind {
+            Some(TileKind::Color { color, .. }) if color == SOLID => TileCollision::Solid,
+            Some(TileKind::Color { color, .. }) if color == ONE_WAY => TileCollision::OneWay,
+            Some(TileKind::Color { color, .. }) if color == SLOPE_UP_RIGHT => {
+                TileCollision::SlopeUpRight
+            }
+            Some(TileKind::Color { color, .. }) if color == SLOPE_UP_LEFT => {
+                TileCollision::SlopeUpLeft
+            }
+            _ => TileCollision::Empty,
+        }))
+        .id();
+
+    commands.spawn_bundle(OrthographicCameraBundle::new_2d());
+
+    let size = Vec2::new(24.0, 40.0);
+    commands
+        .spawn_bundle(SpriteBundle {
+            sprite: Sprite {
+                color: Color::ORANGE,
+                custom_size: Some(size),
+                ..Default::default()
+            },
+            transform: Transform::from_translation(Vec3::new(
+                -window.width() / 2.0 + 100.0,
+                -window.height() / 2.0 + 200.0,
+                10.0,
+            )),
+            ..Default::default()
+        })
+        .insert(KinematicTileBody::new(tile_map_entity, size));
+}
+
+fn control_player(
+    time: Res<Time>,
+    input: Res<Input<KeyCode>>,
+    mut bodies: Query<&mut KinematicTileBody>,
+) {
+    const WALK_SPEED: f32 = 200.0;
+    const JUMP_SPEED: f32 = 500.0;
+    const GRAVITY: f32 = 1200.0;
+
+    for mut body in bodies.iter_mut() {
+        body.velocity.x = 0.0;
+        if input.pressed(KeyCode::Left) {
+            body.velocity.x -= WALK_SPEED;
+        }
+        if input.pressed(KeyCode::Right) {
+            body.velocity.x += WALK_SPEED;
+        }
+
+        if body.on_ground && input.just_pressed(KeyCode::Space) {
+            body.velocity.y = JUMP_SPEED;
+        } else {
+            body.velocity.y -= GRAVITY * time.delta_seconds();
+        }
+    }
+}
+
+fn log_contacts(mut contact_events: EventReader<TileContactEvent>) {
+    for event in contact_events.iter() {
+        if event.normal.x != 0.0 {
+            println!("Hit a wall at {:?}", event.coord);
+        }
+    }
+}
@@ -0,0 +1,151 @@
+use std::sync::Mutex;
+
+use bevy::{input::mouse::MouseWheel, prelude::*, utils::HashMap};
+use bevy_tile_map::prelude::*;
+
+// Controls: W, A, S, D to move the camera, the mouse wheel to zoom and Space to paint the tile in
+// the center of the screen. Painted tiles are saved when their chunk gets unloaded, so they are
+// still there after moving away and coming back.
+
+fn main() {
+    App::new()
+        .add_plugins(DefaultPlugins)
+        .add_plugin(TileMapPlugin)
+        .add_startup_system(setup)
+        .add_system(control_camera)
+        .add_system(paint)
+        .run();
+}
+
+/// Generates hilly terrain and keeps the chunks that got unloaded in memory, where a real game
+/// would write them to a region file.
+#[derive(Default)]
+struct Terrain {
+    saved: Mutex<HashMap<UVec3, Vec<Tile>>>,
+}
+
+impl Terrain {
+    fn generate(coord: UVec2) -> Color {
+        let height = (coord.x as f32 * 0.05).sin() + (coord.y as f32 * 0.07).cos();
+        if height < -0.5 {
+            Color::MIDNIGHT_BLUE
+        } else if height < 0.0 {
+            Color::BEIGE
+        } else if height < 1.2 {
+            Color::DARK_GREEN
+        } else {
+            Color::GRAY
+        }
+    }
+}
+
+impl ChunkProvider for Terrain {
+    fn load(&self, chunk_coord: UVec3, chunk_size: UVec2) -> Option<Vec<Tile>> {
+        if let Some(tiles) = self.saved.lock().unwrap().remove(&chunk_coord) {
+            return Some(tiles);
+        }
+
+        let start = chunk_coord.truncate() * chunk_size;
+        let tiles = (0..chunk_size.y)
+            .flat_map(|y| (0..chunk_size.x).map(move |x| start + UVec2::new(x, y)))
+            .map(|coord| Tile {
+                entity: None,
+                kind: Some(TileKind::Color {
+                    color: Terrain::generate(coord),
+                    transform: TileTransform::default(),
+                }),
+                pickable: false,
+            })
+            .collect();
+        Some(tiles)
+    }
+
+    fn save(&self, chunk_coord: UVec3, tiles: &[Tile]) {
+        self.saved
+            .lock()
+            .unwrap()
+            .insert(chunk_coord, tiles.to_vec());
+    }
+}
+
+fn setup(mut commands: Commands, mut tile_sheets: ResMut<Assets<TileSheet>>) {
+    // The provider only saves generating and spawning chunks that are out of range, the
+    // `TileMap` still holds every tile up front. At 64 bytes per tile this map takes 64 MiB.
+    let tile_map = TileMap::new(
+        UVec2::new(1024, 1024),
+        UVec2::new(32, 32),
+        UVec2::new(16, 16),
+        tile_sheets.add(TileSheet::empty()),
+    );
+
+    let center = (tile_map.size.truncate() * tile_map.tile_size).as_vec2() / 2.0;
+    commands
+        .spawn_bundle(TileMapBundle {
+            tile_map,
+            transform: TransformBundle {
+                local: Transform::from_translation(-center.extend(0.0)),
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .insert(ChunkStreaming::new(800.0, 1000.0))
+        .insert(TileMapChunkProvider::new(Terrain::default()));
+    commands.spawn_bundle(OrthographicCameraBundle::new_2d());
+}
+
+fn paint(
+    input: Res<Input<KeyCode>>,
+    camera: Query<&Transform, With<Camera>>,
+    mut tile_maps: Query<(&mut TileMap, &GlobalTransform)>,
+) {
+    if !input.pressed(KeyCode::Space) {
+        return;
+    }
+
+    for camera in camera.iter() {
+        for (mut tile_map, transform) in tile_maps.iter_mut() {
+            let local = transform
+                .compute_matrix()
+                .inverse()
+                .transform_point3(camera.translation)
+                .truncate();
+            let coord = (local / tile_map.tile_size.as_vec2()).as_uvec2().extend(0);
+
+            if let Some(tile) = tile_map.get_mut(coord) {
+                tile.kind = Some(TileKind::Color {
+                    color: Color::RED,
+                    transform: TileTransform::default(),
+                });
+            }
+        }
+    }
+}
+
+fn control_camera(
+    input: Res<Input<KeyCode>>,
+    mut mouse_wheel_events: EventReader<MouseWheel>,
+    mut camera: Query<(&mut Transform, &mut OrthographicProjection), With<Camera>>,
+) {
+    for (mut transform, mut projection) in camera.iter_mut() {
+        const SPEED: f32 = 20.0;
+
+        if input.pressed(KeyCode::W) {
+            transform.translation.y += SPEED;
+        }
+        if input.pressed(KeyCode::S) {
+            transform.translation.y -= SPEED;
+        }
+        if input.pressed(KeyCode::A) {
+            transform.translation.x -= SPEED;
+        }
+        if input.pressed(KeyCode::D) {
+            transform.translation.x += SPEED;
+        }
+
+        const MOUSE_SPEED: f32 = 0.1;
+
+        for event in mouse_wheel_events.iter() {
+            projection.scale = (projection.scale - event.y * MOUSE_SPEED).max(0.0001);
+        }
+    }
+}
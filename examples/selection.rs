@@ -0,0 +1,152 @@
+use bevy::{input::mouse::MouseWheel, prelude::*};
+use bevy_tile_map::prelude::*;
+
+// Controls: Drag to select tiles, hold shift to add to the selection and control to remove from
+// it. Tab switches between box and lasso selection. F fills the selection, Delete clears it, I
+// inverts it, C copies it and V pastes it at the tile under the cursor.
+
+fn main() {
+    App::new()
+        .add_plugins(DefaultPlugins)
+        .add_plugin(TileMapPlugin)
+        .add_startup_system(setup)
+        .add_system(edit_selection)
+        .add_system(control_camera)
+        .run();
+}
+
+fn setup(
+    mut commands: Commands,
+    windows: Res<Windows>,
+    mut tile_sheets: ResMut<Assets<TileSheet>>,
+) {
+    let mut tile_map = TileMap::new(
+        UVec2::new(64, 64),
+        UVec2::new(16, 16),
+        UVec2::new(16, 16),
+        tile_sheets.add(TileSheet::empty()),
+    );
+
+    for x in 0..tile_map.size.x {
+        for y in 0..tile_map.size.y {
+            let shade = if (x + y) % 2 == 0 { 0.3 } else { 0.35 };
+            tile_map[(x, y, 0)] = Tile {
+                entity: None,
+                kind: Some(TileKind::Color {
+                    color: Color::rgb(shade, shade, shade),
+                    transform: TileTransform::default(),
+                }),
+                pickable: true,
+            };
+        }
+    }
+
+    let window = windows.get_primary().unwrap();
+    commands.spawn_bundle(TileMapBundle {
+        tile_map,
+        transform: TransformBundle {
+            local: Transform::from_translation(Vec3::new(
+                -window.width() / 2.0,
+                -window.height() / 2.0,
+                0.0,
+            )),
+            ..Default::default()
+        },
+        ..Default::default()
+    });
+
+    let mut selector = TileSelector::new(TileSelectMode::Box);
+    selector.highlight = Some(TileHighlight::pulse(Color::rgba(0.2, 0.6, 1.0, 0.6)));
+
+    commands
+        .spawn_bundle(OrthographicCameraBundle::new_2d())
+        .insert(
+            TileMapRayCastSource::default()
+                .with_hover_highlight(TileHighlight::outline(Color::WHITE)),
+        )
+        .insert(selector);
+}
+
+fn edit_selection(
+    input: Res<Input<KeyCode>>,
+    mut clipboard: Local<Vec<(UVec3, Tile)>>,
+    mut selectors: Query<(&mut TileSelector, &TileMapRayCastSource)>,
+    mut tile_maps: Query<&mut TileMap>,
+) {
+    for (mut selector, source) in selectors.iter_mut() {
+        if input.just_pressed(KeyCode::Tab) {
+            selector.mode = match selector.mode {
+                TileSelectMode::Box => TileSelectMode::Lasso,
+                TileSelectMode::Lasso => TileSelectMode::Box,
+            };
+            println!("{:?} selection", selector.mode);
+        }
+
+        let mut tile_map = if let Some(tile_map) = selector
+            .tile_map()
+            .and_then(|entity| tile_maps.get_mut(entity).ok())
+        {
+            tile_map
+        } else {
+            continue;
+        };
+
+        if input.just_pressed(KeyCode::F) {
+            selector.selection().fill(
+                &mut tile_map,
+                Tile {
+                    entity: None,
+                    kind: Some(TileKind::Color {
+                        color: Color::ORANGE_RED,
+                        transform: TileTransform::default(),
+                    }),
+                    pickable: true,
+                },
+            );
+        }
+        if input.just_pressed(KeyCode::Delete) {
+            selector.selection().clear_tiles(&mut tile_map);
+        }
+        if input.just_pressed(KeyCode::I) {
+            let inverted = selector.selection().invert(tile_map.size);
+            *selector.selection_mut() = inverted;
+        }
+        if input.just_pressed(KeyCode::C) {
+            *clipboard = selector.selection().copy(&tile_map);
+        }
+        if input.just_pressed(KeyCode::V) {
+            if let Some(hovered) = source.hovered() {
+                TileSelection::paste(&mut tile_map, &clipboard, hovered.coord);
+            }
+        }
+    }
+}
+
+fn control_camera(
+    input: Res<Input<KeyCode>>,
+    mut mouse_wheel_events: EventReader<MouseWheel>,
+    mut camera: Query<(&mut Transform, &mut OrthographicProjection), With<Camera>>,
+) {
+    for (mut transform, mut projection) in camera.iter_mut() {
+        const SPEED: f32 = 20.0;
+
+        if input.pressed(KeyCode::W) {
+            transform.translation.y += SPEED;
+        }
+        if input.pressed(KeyCode::S) {
+            transform.translation.y -= SPEED;
+        }
+        if input.pressed(KeyCode::A) {
+            transform.translation.x -= SPEED;
+        }
+        if input.pressed(KeyCode::D) {
+            transform.translation.x += SPEED;
+        }
+
+        const MOUSE_SPEED: f32 = 0.1;
+
+        for event in mouse_wheel_events.iter() {
+            projection.scale = (projection.scale - event.y * MOUSE_SPEED).max(0.0001);
+        }
+    }
+}
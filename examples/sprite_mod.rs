@@ -44,7 +44,9 @@ fn setup(
                 entity: None,
                 kind: Some(TileKind::Sprite {
                     idx: 364,
-                    transform: TileTransform::default(),
+                    // Flips and quarter rotations are exact, so they are handy to break up repeating
+                    // floor tiles.
+                    transform: TileTransform::from_flip(TileFlip::from_bits_truncate(rng.gen())),
                     mask_color: Color::WHITE,
                 }),
                 pickable: true,
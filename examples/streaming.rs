@@ -0,0 +1,80 @@
+use bevy::{input::mouse::MouseWheel, prelude::*};
+use bevy_tile_map::prelude::*;
+use rand::Rng;
+
+// Controls: W, A, S, D to move the camera and the mouse wheel to zoom. Zoom out to see the
+// chunks getting spawned and despawned at the edges of the streaming radius.
+
+fn main() {
+    App::new()
+        .add_plugins(DefaultPlugins)
+        .add_plugin(TileMapPlugin)
+        .add_startup_system(setup)
+        .add_system(control_camera)
+        .run();
+}
+
+fn setup(mut commands: Commands, mut tile_sheets: ResMut<Assets<TileSheet>>) {
+    let mut tile_map = TileMap::new(
+        UVec2::new(2048, 2048),
+        UVec2::new(32, 32),
+        UVec2::new(16, 16),
+        tile_sheets.add(TileSheet::empty()),
+    );
+
+    let mut rng = rand::thread_rng();
+    for x in 0..tile_map.size.x {
+        for y in 0..tile_map.size.y {
+            tile_map[(x, y, 0)] = Tile {
+                entity: None,
+                kind: Some(TileKind::Color {
+                    color: Color::rgb(rng.gen(), rng.gen(), rng.gen()),
+                    transform: TileTransform::default(),
+                }),
+                pickable: false,
+            };
+        }
+    }
+
+    let center = (tile_map.size.truncate() * tile_map.tile_size).as_vec2() / 2.0;
+    commands
+        .spawn_bundle(TileMapBundle {
+            tile_map,
+            transform: TransformBundle {
+                local: Transform::from_translation(-center.extend(0.0)),
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .insert(ChunkStreaming::new(800.0, 1000.0));
+    commands.spawn_bundle(OrthographicCameraBundle::new_2d());
+}
+
+fn control_camera(
+    input: Res<Input<KeyCode>>,
+    mut mouse_wheel_events: EventReader<MouseWheel>,
+    mut camera: Query<(&mut Transform, &mut OrthographicProjection), With<Camera>>,
+) {
+    for (mut transform, mut projection) in camera.iter_mut() {
+        const SPEED: f32 = 20.0;
+
+        if input.pressed(KeyCode::W) {
+            transform.translation.y += SPEED;
+        }
+        if input.pressed(KeyCode::S) {
+            transform.translation.y -= SPEED;
+        }
+        if input.pressed(KeyCode::A) {
+            transform.translation.x -= SPEED;
+        }
+        if input.pressed(KeyCode::D) {
+            transform.translation.x += SPEED;
+        }
+
+        const MOUSE_SPEED: f32 = 0.1;
+
+        for event in mouse_wheel_events.iter() {
+            projection.scale = (projection.scale - event.y * MOUSE_SPEED).max(0.0001);
+        }
+    }
+}
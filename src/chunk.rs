@@ -1,73 +1,102 @@
 use bevy::{
     prelude::*,
-    render::{
-        mesh::{Indices, PrimitiveTopology},
-        primitives::Aabb,
-    },
-    utils::HashMap,
+    render::primitives::Aabb,
+    utils::{HashMap, Instant},
 };
 
-use crate::{interaction::TileMapRayCastMesh, Tile, TileMap, TileSheet};
+#[cfg(feature = "render")]
+use crate::render::ChunkHighlights;
+use crate::{diagnostics::CoreStats, streaming::ChunkStreaming, Tile, TileMap, TileSheet};
 
 #[derive(Debug, Default, Component, Clone, Copy, Deref, DerefMut, PartialEq, Eq, Hash)]
 pub struct ChunkCoord(pub UVec3);
 
+/// The entities of the chunks of a tile map. Chunks without any tiles are not spawned.
 #[derive(Debug, Default, Component, Deref, DerefMut)]
 pub struct ChunkEntities(HashMap<ChunkCoord, Entity>);
 
 #[derive(Default, Bundle)]
 pub struct ChunkBundle {
     data: ChunkData,
-    mesh: Handle<Mesh>,
-    aabb: Aabb,
     #[bundle]
     transform: TransformBundle,
+}
+
+#[cfg(feature = "render")]
+#[derive(Default, Bundle)]
+struct ChunkRenderBundle {
+    aabb: Aabb,
+    highlights: ChunkHighlights,
     visibility: Visibility,
     computed_visibility: ComputedVisibility,
-    picking: TileMapRayCastMesh,
 }
 
+#[allow(clippy::type_complexity)]
 pub fn generate_or_update_chunks(
-    mut mesh_cache: Local<HashMap<UVec2, (Aabb, Handle<Mesh>)>>,
     mut commands: Commands,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut tile_maps: Query<(Entity, &mut ChunkEntities, &mut TileMap)>,
-    mut chunk_meshs: Query<(&mut Aabb, &mut Handle<Mesh>, &mut ChunkData), Without<TileMap>>,
+    mut tile_maps: Query<(
+        Entity,
+        &mut ChunkEntities,
+        &mut TileMap,
+        Option<&ChunkStreaming>,
+    )>,
+    mut chunks: Query<(&mut ChunkData, Option<&mut Aabb>), Without<TileMap>>,
+    stats: Option<ResMut<CoreStats>>,
 ) {
-    for (entity, mut chunk_entities, mut tile_map) in tile_maps.iter_mut() {
+    let start = Instant::now();
+    let mut dirty_chunks = 0;
+
+    for (entity, mut chunk_entities, mut tile_map, streaming) in tile_maps.iter_mut() {
         if tile_map.dirty_chunks.len() == 0 {
             continue;
         }
 
         let screen_chunk_size = tile_map.chunk_size * tile_map.tile_size;
-        let (new_aabb, new_mesh) = mesh_cache.entry(screen_chunk_size).or_insert_with(|| {
-            let mesh = plane_mesh(screen_chunk_size.as_vec2());
-            let aabb = mesh.compute_aabb().unwrap();
-            (aabb, meshes.add(mesh))
-        });
+        let new_aabb = Aabb::from_min_max(Vec3::ZERO, screen_chunk_size.as_vec2().extend(0.0));
 
         for chunk_coord in tile_map.dirty_chunks.drain().collect::<Vec<_>>() {
-            if let Some(chunk) = chunk_entities.get(&chunk_coord) {
-                let (mut aabb, mut mesh, mut chunk_data) = chunk_meshs
-                    .get_mut(*chunk)
+            // Streamed chunks get marked dirty again when they are loaded.
+            if matches!(streaming, Some(streaming) if !streaming.is_loaded(chunk_coord)) {
+                continue;
+            }
+            dirty_chunks += 1;
+
+            if let Some(chunk) = chunk_entities.get(&chunk_coord).copied() {
+                let (mut chunk_data, aabb) = chunks
+                    .get_mut(chunk)
                     .expect("A chunk for a tile map is missing");
 
-                *aabb = new_aabb.clone();
-                *mesh = new_mesh.as_weak();
                 chunk_data.sync(&tile_map);
+                if chunk_data.is_empty() {
+                    // Dropping the chunk also frees its render resources.
+                    commands.entity(chunk).despawn_recursive();
+                    chunk_entities.remove(&chunk_coord);
+                    continue;
+                }
+
+                if let Some(mut aabb) = aabb {
+                    *aabb = new_aabb.clone();
+                }
             } else {
+                let data = ChunkData::new(chunk_coord, &tile_map, tile_map.tile_sheet.as_weak());
+                if data.is_empty() {
+                    continue;
+                }
+
                 commands.entity(entity).with_children(|child_builder| {
                     #[allow(unused_mut)]
                     let mut entity_commands = child_builder.spawn_bundle(ChunkBundle {
-                        mesh: new_mesh.as_weak(),
-                        aabb: new_aabb.clone(),
-                        data: ChunkData::new(chunk_coord, &tile_map, tile_map.tile_sheet.as_weak()),
+                        data,
                         transform: TransformBundle {
                             local: Transform::from_translation(
                                 (chunk_coord.0 * screen_chunk_size.extend(1)).as_vec3(),
                             ),
                             ..Default::default()
                         },
+                    });
+                    #[cfg(feature = "render")]
+                    entity_commands.insert_bundle(ChunkRenderBundle {
+                        aabb: new_aabb.clone(),
                         ..Default::default()
                     });
 
@@ -76,6 +105,11 @@ pub fn generate_or_update_chunks(
             }
         }
     }
+
+    if let Some(mut stats) = stats {
+        stats.dirty_chunks = dirty_chunks;
+        stats.generate_chunks_time = start.elapsed();
+    }
 }
 
 #[derive(Debug, Default, Component, Clone)]
@@ -146,6 +180,13 @@ impl ChunkData {
         }
     }
 
+    /// True if none of the tiles is drawn or can be picked.
+    pub fn is_empty(&self) -> bool {
+        self.tiles
+            .iter()
+            .all(|tile| tile.kind.is_none() && !tile.pickable)
+    }
+
     pub fn tiles(&self) -> &Vec<Tile> {
         &self.tiles
     }
@@ -162,30 +203,3 @@ impl ChunkData {
         &self.tile_sheet
     }
 }
-
-pub fn plane_mesh(size: Vec2) -> Mesh {
-    let vertices = [
-        ([0.0, 0.0, 0.0], [0.0, 0.0, 1.0], [0.0, 0.0]),
-        ([0.0, size.y, 0.0], [0.0, 0.0, 1.0], [0.0, 1.0]),
-        ([size.x, size.y, 0.0], [0.0, 0.0, 1.0], [1.0, 1.0]),
-        ([size.x, 0.0, 0.0], [0.0, 0.0, 1.0], [1.0, 0.0]),
-    ];
-
-    let indices = Indices::U16(vec![0, 2, 1, 0, 3, 2]);
-
-    let mut positions = Vec::new();
-    let mut normals = Vec::new();
-    let mut uvs = Vec::new();
-    for (position, normal, uv) in &vertices {
-        positions.push(*position);
-        normals.push(*normal);
-        uvs.push(*uv);
-    }
-
-    let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
-    mesh.set_indices(Some(indices));
-    mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, positions);
-    mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, normals);
-    mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, uvs);
-    mesh
-}
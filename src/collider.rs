@@ -0,0 +1,526 @@
+use bevy::{
+    prelude::*,
+    utils::{HashMap, HashSet},
+};
+
+use crate::{Tile, TileMap};
+
+/// An axis aligned rectangle in either the local space of the tile map or in world space.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ColliderRect {
+    pub min: Vec2,
+    pub max: Vec2,
+}
+
+impl ColliderRect {
+    #[inline]
+    pub fn size(&self) -> Vec2 {
+        self.max - self.min
+    }
+
+    #[inline]
+    pub fn center(&self) -> Vec2 {
+        (self.min + self.max) / 2.0
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ColliderSpace {
+    #[default]
+    /// Positions are relative to the tile map entity, in the same units as `TileMap::tile_size`.
+    Local,
+    /// Positions have the `GlobalTransform` of the tile map applied to them. Rects stay axis
+    /// aligned, so they are only exact if the transform doesn't rotate the map. Use `Local` and
+    /// transform the shapes yourself for rotated maps, outlines are exact either way.
+    World,
+}
+
+/// Add this next to a `TileMap` to have `TileMapColliders` generated for it. A tile is solid if
+/// `is_solid` returns true for it on any of the given layers.
+#[derive(Component)]
+pub struct TileMapColliderGenerator {
+    pub layers: Vec<u32>,
+    pub space: ColliderSpace,
+    is_solid: Box<dyn Fn(&Tile) -> bool + Send + Sync>,
+}
+
+impl TileMapColliderGenerator {
+    pub fn new(
+        layers: Vec<u32>,
+        space: ColliderSpace,
+        is_solid: impl Fn(&Tile) -> bool + Send + Sync + 'static,
+    ) -> Self {
+        TileMapColliderGenerator {
+            layers,
+            space,
+            is_solid: Box::new(is_solid),
+        }
+    }
+
+    /// Every tile with a `kind` is solid.
+    pub fn any_tile(layers: Vec<u32>, space: ColliderSpace) -> Self {
+        Self::new(layers, space, |tile| tile.kind.is_some())
+    }
+
+    fn is_solid(&self, tile_map: &TileMap, coord: UVec2) -> bool {
+        self.layers.iter().any(
+            |layer| matches!(tile_map.get(coord.extend(*layer)), Some(tile) if (self.is_solid)(tile)),
+        )
+    }
+}
+
+/// The collider shapes of a single chunk.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct ChunkColliders {
+    pub rects: Vec<ColliderRect>,
+    /// Closed counter-clockwise loops around the solid tiles. The first point is not repeated at
+    /// the end.
+    pub outlines: Vec<Vec<Vec2>>,
+}
+
+impl ChunkColliders {
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.rects.is_empty() && self.outlines.is_empty()
+    }
+}
+
+/// Collider shapes generated from the solid tiles of a `TileMap`. Shapes are merged per chunk, so
+/// a solid area that crosses a chunk border is split in to one shape per chunk.
+#[derive(Debug, Default, Component)]
+pub struct TileMapColliders {
+    chunks: HashMap<UVec2, ChunkColliders>,
+    updated_chunks: Vec<UVec2>,
+}
+
+impl TileMapColliders {
+    #[inline]
+    pub fn chunk(&self, chunk_coord: UVec2) -> Option<&ChunkColliders> {
+        self.chunks.get(&chunk_coord)
+    }
+
+    #[inline]
+    pub fn chunks(&self) -> impl Iterator<Item = (&UVec2, &ChunkColliders)> {
+        self.chunks.iter()
+    }
+
+    /// The chunks that got regenerated in the last update. Use this to only rebuild the physics
+    /// bodies that actually changed.
+    #[inline]
+    pub fn updated_chunks(&self) -> &[UVec2] {
+        &self.updated_chunks
+    }
+
+    pub fn rects(&self) -> impl Iterator<Item = &ColliderRect> {
+        self.chunks.values().flat_map(|chunk| chunk.rects.iter())
+    }
+
+    pub fn outlines(&self) -> impl Iterator<Item = &Vec<Vec2>> {
+        self.chunks.values().flat_map(|chunk| chunk.outlines.iter())
+    }
+
+    fn update_chunks(
+        &mut self,
+        tile_map: &TileMap,
+        generator: &TileMapColliderGenerator,
+        chunk_coords: HashSet<UVec2>,
+        transform: Option<GlobalTransform>,
+    ) {
+        self.updated_chunks.clear();
+
+        for chunk_coord in chunk_coords {
+            let chunk_colliders =
+                build_chunk_colliders(tile_map, generator, chunk_coord, transform);
+
+            if chunk_colliders.is_empty() {
+                self.chunks.remove(&chunk_coord);
+            } else {
+                self.chunks.insert(chunk_coord, chunk_colliders);
+            }
+            self.updated_chunks.push(chunk_coord);
+        }
+    }
+}
+
+#[allow(clippy::type_complexity)]
+pub(crate) fn update_tile_map_colliders(
+    mut commands: Commands,
+    mut tile_maps: Query<(
+        Entity,
+        &TileMap,
+        &TileMapColliderGenerator,
+        ChangeTrackers<TileMapColliderGenerator>,
+        Option<&GlobalTransform>,
+        Option<ChangeTrackers<GlobalTransform>>,
+        Option<&mut TileMapColliders>,
+    )>,
+) {
+    for (entity, tile_map, generator, generator_tracker, transform, transform_tracker, colliders) in
+        tile_maps.iter_mut()
+    {
+        let world_space_moved = generator.space == ColliderSpace::World
+            && matches!(transform_tracker, Some(tracker) if tracker.is_changed());
+
+        let rebuild_all =
+            colliders.is_none() || generator_tracker.is_changed() || world_space_moved;
+        let dirty: HashSet<UVec2> = if rebuild_all {
+            tile_map
+                .chunks()
+                .into_iter()
+                .map(|chunk_coord| chunk_coord.truncate())
+                .collect()
+        } else {
+            tile_map
+                .dirty_chunks
+                .iter()
+                .filter(|chunk_coord| generator.layers.contains(&chunk_coord.z))
+                .map(|chunk_coord| chunk_coord.truncate())
+                .collect()
+        };
+
+        if dirty.is_empty() {
+            if let Some(mut colliders) = colliders {
+                if !colliders.updated_chunks.is_empty() {
+                    colliders.updated_chunks.clear();
+                }
+            }
+            continue;
+        }
+
+        let transform = match generator.space {
+            ColliderSpace::Local => None,
+            ColliderSpace::World => Some(transform.copied().unwrap_or_default()),
+        };
+
+        if let Some(mut colliders) = colliders {
+            if rebuild_all {
+                colliders.chunks.clear();
+            }
+            colliders.update_chunks(tile_map, generator, dirty, transform);
+        } else {
+            let mut colliders = TileMapColliders::default();
+            colliders.update_chunks(tile_map, generator, dirty, transform);
+            commands.entity(entity).insert(colliders);
+        }
+    }
+}
+
+fn build_chunk_colliders(
+    tile_map: &TileMap,
+    generator: &TileMapColliderGenerator,
+    chunk_coord: UVec2,
+    transform: Option<GlobalTransform>,
+) -> ChunkColliders {
+    let start = chunk_coord * tile_map.chunk_size;
+    let size = (tile_map.size.truncate() - start).min(tile_map.chunk_size);
+
+    let mut solid = vec![false; (size.x * size.y) as usize];
+    for y in 0..size.y {
+        for x in 0..size.x {
+            solid[(y * size.x + x) as usize] =
+                generator.is_solid(tile_map, start + UVec2::new(x, y));
+        }
+    }
+
+    let tile_size = tile_map.tile_size.as_vec2();
+    let to_space = |grid_point: UVec2| {
+        let local = (start + grid_point).as_vec2() * tile_size;
+        if let Some(transform) = transform {
+            transform.mul_vec3(local.extend(0.0)).truncate()
+        } else {
+            local
+        }
+    };
+
+    let rects = merge_rects(&solid, size)
+        .into_iter()
+        .map(|(min, max)| {
+            let (a, b) = (to_space(min), to_space(max));
+            ColliderRect {
+                min: a.min(b),
+                max: a.max(b),
+            }
+        })
+        .collect();
+
+    let outlines = trace_outlines(&solid, size)
+        .into_iter()
+        .map(|outline| outline.into_iter().map(to_space).collect())
+        .collect();
+
+    ChunkColliders { rects, outlines }
+}
+
+/// Greedily merges solid cells in to rectangles, first along x and then along y. Returns the
+/// min and max corners in grid coordinates.
+fn merge_rects(solid: &[bool], size: UVec2) -> Vec<(UVec2, UVec2)> {
+    let idx = |x: u32, y: u32| (y * size.x + x) as usize;
+    let mut used = vec![false; solid.len()];
+    let mut rects = Vec::new();
+
+    for y in 0..size.y {
+        let mut x = 0;
+        while x < size.x {
+            if !solid[idx(x, y)] || used[idx(x, y)] {
+                x += 1;
+                continue;
+            }
+
+            let mut width = 1;
+            while x + width < size.x && solid[idx(x + width, y)] && !used[idx(x + width, y)] {
+                width += 1;
+            }
+
+            let mut height = 1;
+            'grow: while y + height < size.y {
+                for dx in 0..width {
+                    if !solid[idx(x + dx, y + height)] || used[idx(x + dx, y + height)] {
+                        break 'grow;
+                    }
+                }
+                height += 1;
+            }
+
+            for dy in 0..height {
+                for dx in 0..width {
+                    used[idx(x + dx, y + dy)] = true;
+                }
+            }
+
+            rects.push((UVec2::new(x, y), UVec2::new(x + width, y + height)));
+            x += width;
+        }
+    }
+
+    rects
+}
+
+/// Walks the edges between solid and empty cells in to closed counter-clockwise loops, dropping
+/// the points in the middle of straight runs. Returns the points in grid coordinates.
+fn trace_outlines(solid: &[bool], size: UVec2) -> Vec<Vec<UVec2>> {
+    let is_solid = |x: i32, y: i32| {
+        x >= 0
+            && y >= 0
+            && (x as u32) < size.x
+            && (y as u32) < size.y
+            && solid[(y as u32 * size.x + x as u32) as usize]
+    };
+
+    // Every edge keeps the solid cell on its left.
+    let mut edges: HashMap<IVec2, Vec<IVec2>> = HashMap::default();
+    let mut add_edge = |from: IVec2, to: IVec2| edges.entry(from).or_default().push(to);
+    for y in 0..size.y as i32 {
+        for x in 0..size.x as i32 {
+            if !is_solid(x, y) {
+                continue;
+            }
+
+            if !is_solid(x, y - 1) {
+                add_edge(IVec2::new(x, y), IVec2::new(x + 1, y));
+            }
+            if !is_solid(x + 1, y) {
+                add_edge(IVec2::new(x + 1, y), IVec2::new(x + 1, y + 1));
+            }
+            if !is_solid(x, y + 1) {
+                add_edge(IVec2::new(x + 1, y + 1), IVec2::new(x, y + 1));
+            }
+            if !is_solid(x - 1, y) {
+                add_edge(IVec2::new(x, y + 1), IVec2::new(x, y));
+            }
+        }
+    }
+
+    let mut outlines = Vec::new();
+    while let Some((&start, targets)) = edges.iter().next() {
+        let first = targets[0];
+        let mut points = Vec::new();
+        let mut edge = (start, first);
+        remove_edge(&mut edges, edge);
+
+        loop {
+            points.push(edge.0);
+            let (from, to) = edge;
+
+            let mut targets = edges.get(&to).cloned().unwrap_or_default();
+            if to == start {
+                targets.push(first);
+            }
+            // Cells that only touch at a corner share a vertex with two outgoing edges. Always
+            // turning left there keeps them in separate loops that don't touch themselves.
+            let direction = to - from;
+            let next = targets
+                .into_iter()
+                .max_by_key(|target| {
+                    let turn = *target - to;
+                    direction.x * turn.y - direction.y * turn.x
+                })
+                .unwrap();
+
+            if to == start && next == first {
+                break;
+            }
+            edge = (to, next);
+            remove_edge(&mut edges, edge);
+        }
+
+        outlines.push(simplify_loop(points));
+    }
+
+    outlines
+}
+
+fn remove_edge(edges: &mut HashMap<IVec2, Vec<IVec2>>, (from, to): (IVec2, IVec2)) {
+    let targets = edges.get_mut(&from).unwrap();
+    targets.retain(|target| *target != to);
+    if targets.is_empty() {
+        edges.remove(&from);
+    }
+}
+
+fn simplify_loop(points: Vec<IVec2>) -> Vec<UVec2> {
+    let len = points.len();
+    (0..len)
+        .filter(|&i| {
+            let prev = points[(i + len - 1) % len];
+            let next = points[(i + 1) % len];
+            let (a, b) = (points[i] - prev, next - points[i]);
+            a.x * b.y - a.y * b.x != 0
+        })
+        .map(|i| points[i].as_uvec2())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{TileKind, TileTransform};
+
+    fn grid(rows: &[&str]) -> (Vec<bool>, UVec2) {
+        // The first row is the top of the grid.
+        let size = UVec2::new(rows[0].len() as u32, rows.len() as u32);
+        let solid = rows
+            .iter()
+            .rev()
+            .flat_map(|row| row.chars().map(|c| c == '#'))
+            .collect();
+        (solid, size)
+    }
+
+    fn signed_area(outline: &[UVec2]) -> i32 {
+        let points: Vec<IVec2> = outline.iter().map(|point| point.as_ivec2()).collect();
+        (0..points.len())
+            .map(|i| {
+                let (a, b) = (points[i], points[(i + 1) % points.len()]);
+                a.x * b.y - a.y * b.x
+            })
+            .sum::<i32>()
+    }
+
+    fn has_repeated_points(outline: &[UVec2]) -> bool {
+        let points: HashSet<UVec2> = outline.iter().copied().collect();
+        points.len() != outline.len()
+    }
+
+    #[test]
+    fn merge_rects_covers_every_solid_cell_once() {
+        let (solid, size) = grid(&["##.", "###", "###"]);
+        let rects = merge_rects(&solid, size);
+
+        assert_eq!(
+            rects,
+            vec![
+                (UVec2::new(0, 0), UVec2::new(3, 2)),
+                (UVec2::new(0, 2), UVec2::new(2, 3)),
+            ]
+        );
+    }
+
+    #[test]
+    fn colliders_cover_partial_edge_chunks() {
+        let mut tile_map = TileMap::new(
+            UVec2::new(10, 10),
+            UVec2::new(4, 4),
+            UVec2::ONE,
+            Handle::default(),
+        );
+        tile_map[UVec3::new(9, 9, 0)].kind = Some(TileKind::Color {
+            color: Color::WHITE,
+            transform: TileTransform::default(),
+        });
+        let generator = TileMapColliderGenerator::any_tile(vec![0], ColliderSpace::Local);
+
+        let chunks: HashSet<UVec2> = tile_map
+            .chunks()
+            .into_iter()
+            .map(|chunk_coord| chunk_coord.truncate())
+            .collect();
+        assert_eq!(chunks.len(), 9);
+
+        let mut colliders = TileMapColliders::default();
+        colliders.update_chunks(&tile_map, &generator, chunks, None);
+        assert_eq!(
+            colliders.chunk(UVec2::new(2, 2)).unwrap().rects,
+            vec![ColliderRect {
+                min: Vec2::new(9.0, 9.0),
+                max: Vec2::new(10.0, 10.0),
+            }]
+        );
+    }
+
+    #[test]
+    fn merge_rects_empty_grid() {
+        let (solid, size) = grid(&["...", "..."]);
+        assert!(merge_rects(&solid, size).is_empty());
+    }
+
+    #[test]
+    fn trace_outlines_square_is_counter_clockwise() {
+        let (solid, size) = grid(&["##", "##"]);
+        let outlines = trace_outlines(&solid, size);
+
+        assert_eq!(outlines.len(), 1);
+        assert_eq!(outlines[0].len(), 4);
+        assert_eq!(signed_area(&outlines[0]), 2 * 4);
+    }
+
+    #[test]
+    fn trace_outlines_hole_is_clockwise() {
+        let (solid, size) = grid(&["###", "#.#", "###"]);
+        let mut outlines = trace_outlines(&solid, size);
+        outlines.sort_by_key(|outline| signed_area(outline));
+
+        assert_eq!(outlines.len(), 2);
+        assert_eq!(signed_area(&outlines[0]), -2);
+        assert_eq!(signed_area(&outlines[1]), 2 * 9);
+    }
+
+    #[test]
+    fn trace_outlines_splits_cells_touching_at_a_corner() {
+        for rows in [[".#", "#."], ["#.", ".#"]] {
+            let (solid, size) = grid(&rows);
+            // The order of the edges in the hash map changes where tracing starts.
+            for _ in 0..16 {
+                let outlines = trace_outlines(&solid, size);
+
+                assert_eq!(outlines.len(), 2);
+                for outline in &outlines {
+                    assert_eq!(outline.len(), 4);
+                    assert!(!has_repeated_points(outline));
+                    assert_eq!(signed_area(outline), 2);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn trace_outlines_staircase_has_no_self_touching_loops() {
+        let (solid, size) = grid(&["#..#", ".#.#", "#.##", ".#.."]);
+        let outlines = trace_outlines(&solid, size);
+
+        assert_eq!(outlines.len(), 5);
+        for outline in &outlines {
+            assert!(!has_repeated_points(outline));
+            assert!(signed_area(outline) > 0);
+        }
+    }
+}
@@ -0,0 +1,130 @@
+use bevy::{
+    diagnostic::{Diagnostic, DiagnosticId, Diagnostics},
+    prelude::*,
+    utils::Duration,
+};
+
+#[cfg(feature = "render")]
+use crate::render::RenderStats;
+use crate::ChunkData;
+
+/// Adds tile map measurements to the `Diagnostics`, for example to print them with the
+/// `LogDiagnosticsPlugin`. The render measurements need the `TileMapRenderPlugin` and are one
+/// frame behind.
+pub struct TileMapDiagnosticsPlugin;
+
+impl Plugin for TileMapDiagnosticsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<CoreStats>()
+            .add_startup_system(Self::setup_system)
+            .add_system_to_stage(CoreStage::Last, Self::diagnostic_system);
+
+        #[cfg(feature = "render")]
+        {
+            let render_stats = RenderStats::default();
+            app.insert_resource(render_stats.clone());
+            if let Ok(render_app) = app.get_sub_app_mut(bevy::render::RenderApp) {
+                render_app.insert_resource(render_stats);
+            }
+        }
+    }
+
+    fn name(&self) -> &str {
+        "Tilemap Diagnostics Plugin"
+    }
+}
+
+impl TileMapDiagnosticsPlugin {
+    /// The number of spawned chunk entities.
+    pub const CHUNKS: DiagnosticId =
+        DiagnosticId::from_u128(189541864843673781664151174363120868017);
+    /// The number of chunks rebuilt by `generate_or_update_chunks`.
+    pub const DIRTY_CHUNKS: DiagnosticId =
+        DiagnosticId::from_u128(95337510130712517605106555557597159540);
+    pub const SYNC_AS_TILES_TIME: DiagnosticId =
+        DiagnosticId::from_u128(88316817477619585963706931599480508317);
+    pub const GENERATE_CHUNKS_TIME: DiagnosticId =
+        DiagnosticId::from_u128(270119067997948857965658012047319593218);
+    /// The number of visible chunks extracted to the render world.
+    pub const EXTRACTED_CHUNKS: DiagnosticId =
+        DiagnosticId::from_u128(144054347334200110406427981633885049268);
+    /// The bytes of tile data written to the GPU.
+    pub const UPLOADED_BYTES: DiagnosticId =
+        DiagnosticId::from_u128(145366476573503882371652347868558864071);
+    /// The number of chunk draw calls, summed over the views.
+    pub const DRAW_CALLS: DiagnosticId =
+        DiagnosticId::from_u128(142643160806411250371635066044075402346);
+
+    pub fn setup_system(mut diagnostics: ResMut<Diagnostics>) {
+        diagnostics.add(Diagnostic::new(Self::CHUNKS, "tile_map_chunks", 20));
+        diagnostics.add(Diagnostic::new(
+            Self::DIRTY_CHUNKS,
+            "tile_map_dirty_chunks",
+            20,
+        ));
+        diagnostics.add(
+            Diagnostic::new(Self::SYNC_AS_TILES_TIME, "tile_map_sync_as_tiles_time", 20)
+                .with_suffix("ms"),
+        );
+        diagnostics.add(
+            Diagnostic::new(
+                Self::GENERATE_CHUNKS_TIME,
+                "tile_map_generate_chunks_time",
+                20,
+            )
+            .with_suffix("ms"),
+        );
+
+        #[cfg(feature = "render")]
+        {
+            diagnostics.add(Diagnostic::new(
+                Self::EXTRACTED_CHUNKS,
+                "tile_map_extracted_chunks",
+                20,
+            ));
+            diagnostics.add(
+                Diagnostic::new(Self::UPLOADED_BYTES, "tile_map_uploaded_bytes", 20)
+                    .with_suffix("B"),
+            );
+            diagnostics.add(Diagnostic::new(Self::DRAW_CALLS, "tile_map_draw_calls", 20));
+        }
+    }
+
+    pub fn diagnostic_system(
+        mut diagnostics: ResMut<Diagnostics>,
+        mut core_stats: ResMut<CoreStats>,
+        #[cfg(feature = "render")] render_stats: Res<RenderStats>,
+        chunks: Query<(), With<ChunkData>>,
+    ) {
+        diagnostics.add_measurement(Self::CHUNKS, chunks.iter().count() as f64);
+
+        let core_stats = std::mem::take(&mut *core_stats);
+        diagnostics.add_measurement(Self::DIRTY_CHUNKS, core_stats.dirty_chunks as f64);
+        diagnostics.add_measurement(
+            Self::SYNC_AS_TILES_TIME,
+            core_stats.sync_as_tiles_time.as_secs_f64() * 1000.0,
+        );
+        diagnostics.add_measurement(
+            Self::GENERATE_CHUNKS_TIME,
+            core_stats.generate_chunks_time.as_secs_f64() * 1000.0,
+        );
+
+        #[cfg(feature = "render")]
+        {
+            let render_stats = render_stats.get();
+            diagnostics
+                .add_measurement(Self::EXTRACTED_CHUNKS, render_stats.extracted_chunks as f64);
+            diagnostics.add_measurement(Self::UPLOADED_BYTES, render_stats.uploaded_bytes as f64);
+            diagnostics.add_measurement(Self::DRAW_CALLS, render_stats.draw_calls as f64);
+        }
+    }
+}
+
+/// Measurements of the core systems of the current frame, only taken with the
+/// `TileMapDiagnosticsPlugin`.
+#[derive(Debug, Default)]
+pub struct CoreStats {
+    pub(crate) dirty_chunks: usize,
+    pub(crate) sync_as_tiles_time: Duration,
+    pub(crate) generate_chunks_time: Duration,
+}
@@ -0,0 +1,120 @@
+use bevy::{
+    prelude::*,
+    utils::{HashMap, HashSet},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TileHighlightStyle {
+    /// Blends the color over the whole tile.
+    Tint,
+    /// Draws a two pixel wide border in the color along the edge of the tile.
+    Outline,
+    /// Like `Tint`, with the alpha of the color fading in and out.
+    Pulse,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TileHighlight {
+    pub color: Color,
+    pub style: TileHighlightStyle,
+}
+
+impl TileHighlight {
+    pub fn tint(color: Color) -> Self {
+        TileHighlight {
+            color,
+            style: TileHighlightStyle::Tint,
+        }
+    }
+
+    pub fn outline(color: Color) -> Self {
+        TileHighlight {
+            color,
+            style: TileHighlightStyle::Outline,
+        }
+    }
+
+    pub fn pulse(color: Color) -> Self {
+        TileHighlight {
+            color,
+            style: TileHighlightStyle::Pulse,
+        }
+    }
+}
+
+/// Highlights drawn over the tiles of the `TileMap` next to it, without changing its tiles. Only
+/// tiles in chunks with at least one drawn or pickable tile can be highlighted.
+#[derive(Debug, Default, Clone, Component)]
+pub struct TileHighlights {
+    tiles: HashMap<UVec3, TileHighlight>,
+    // The hover and selection highlights of the interaction plugin, by the entity they belong
+    // to. They are drawn instead of `tiles`.
+    generated: HashMap<Entity, HashMap<UVec3, TileHighlight>>,
+    pub(crate) dirty: HashSet<UVec3>,
+}
+
+impl TileHighlights {
+    pub fn insert(&mut self, coord: UVec3, highlight: TileHighlight) -> Option<TileHighlight> {
+        self.dirty.insert(coord);
+        self.tiles.insert(coord, highlight)
+    }
+
+    pub fn remove(&mut self, coord: UVec3) -> Option<TileHighlight> {
+        self.dirty.insert(coord);
+        self.tiles.remove(&coord)
+    }
+
+    pub fn clear(&mut self) {
+        self.dirty
+            .extend(self.tiles.drain().map(|(coord, _)| coord));
+    }
+
+    /// The highlight drawn over the tile at `coord`.
+    pub fn get(&self, coord: UVec3) -> Option<&TileHighlight> {
+        self.generated
+            .iter()
+            .filter_map(|(owner, highlights)| Some((owner, highlights.get(&coord)?)))
+            .min_by_key(|(owner, _)| **owner)
+            .map(|(_, highlight)| highlight)
+            .or_else(|| self.tiles.get(&coord))
+    }
+
+    #[cfg(feature = "interaction")]
+    pub(crate) fn generated(&self, owner: Entity) -> Option<&HashMap<UVec3, TileHighlight>> {
+        self.generated.get(&owner)
+    }
+
+    #[cfg(feature = "interaction")]
+    pub(crate) fn generated_owners(&self) -> impl Iterator<Item = Entity> + '_ {
+        self.generated.keys().copied()
+    }
+
+    /// Replaces the highlights of `owner`, an empty map removes them.
+    #[cfg(feature = "interaction")]
+    pub(crate) fn set_generated(
+        &mut self,
+        owner: Entity,
+        highlights: HashMap<UVec3, TileHighlight>,
+    ) {
+        let old = if highlights.is_empty() {
+            self.generated.remove(&owner)
+        } else {
+            self.generated.insert(owner, highlights)
+        }
+        .unwrap_or_default();
+        let new = self.generated.get(&owner);
+
+        self.dirty.extend(
+            old.iter()
+                .filter(|(coord, highlight)| new.and_then(|new| new.get(coord)) != Some(highlight))
+                .map(|(coord, _)| *coord),
+        );
+        if let Some(new) = new {
+            self.dirty.extend(
+                new.iter()
+                    .filter(|(coord, highlight)| old.get(coord) != Some(highlight))
+                    .map(|(coord, _)| *coord),
+            );
+        }
+    }
+}
@@ -0,0 +1,168 @@
+use bevy::prelude::*;
+
+use super::{TileHit, TileMapRayCastSource, TileRay};
+use crate::TileMap;
+
+/// Intersects the rays with the layers of every tile map. Layer `n` of a tile map is `n` units in
+/// front of it, like its chunks are.
+pub(crate) fn cast_rays(
+    mut sources: Query<&mut TileMapRayCastSource>,
+    tile_maps: Query<(Entity, &TileMap, &GlobalTransform)>,
+) {
+    for mut source in sources.iter_mut() {
+        let source = &mut *source;
+        source.hits.clear();
+
+        let ray = if let Some(ray) = source.ray {
+            ray
+        } else {
+            continue;
+        };
+
+        for (entity, tile_map, transform) in tile_maps.iter() {
+            let to_world = transform.compute_matrix();
+            for (coord, local) in layer_hits(ray, tile_map, to_world) {
+                if tile_map[coord].pickable {
+                    let position = to_world.transform_point3(local);
+                    source.hits.push(TileHit {
+                        tile_map: entity,
+                        coord,
+                        position,
+                        tile_position: local.truncate()
+                            - (coord.truncate() * tile_map.tile_size).as_vec2(),
+                        distance: position.distance(ray.origin),
+                    });
+                }
+            }
+        }
+    }
+}
+
+/// The coord of the tile the ray goes through on each layer of the tile map, and where it goes
+/// through it in the local space of the tile map. Layers behind the origin of the ray or hit
+/// outside of the map are skipped.
+fn layer_hits(ray: TileRay, tile_map: &TileMap, to_world: Mat4) -> Vec<(UVec3, Vec3)> {
+    let to_local = to_world.inverse();
+    let origin = to_local.transform_point3(ray.origin);
+    let direction = to_local.transform_vector3(ray.direction);
+    if direction.z.abs() <= f32::EPSILON {
+        return Vec::new();
+    }
+
+    let mut hits = Vec::new();
+    for layer in 0..tile_map.size.z {
+        let t = (layer as f32 - origin.z) / direction.z;
+        let local = origin + direction * t;
+        if t < 0.0 || local.x < 0.0 || local.y < 0.0 {
+            continue;
+        }
+
+        let coord = (local.truncate() / tile_map.tile_size.as_vec2())
+            .as_uvec2()
+            .extend(layer);
+        if coord.x >= tile_map.size.x || coord.y >= tile_map.size.y {
+            continue;
+        }
+
+        hits.push((coord, local));
+    }
+    hits
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A 4x4 map of 16x16 tiles with 3 layers, moved to (100, 50, 10) and scaled by 2.
+    fn tile_map() -> (TileMap, Mat4) {
+        let mut tile_map = TileMap::new(
+            UVec2::new(4, 4),
+            UVec2::new(4, 4),
+            UVec2::new(16, 16),
+            Handle::default(),
+        );
+        tile_map.add_empty_layer();
+        tile_map.add_empty_layer();
+        let transform = Transform::from_xyz(100.0, 50.0, 10.0).with_scale(Vec3::splat(2.0));
+        (tile_map, transform.compute_matrix())
+    }
+
+    fn ray_down(x: f32, y: f32) -> TileRay {
+        TileRay {
+            origin: Vec3::new(x, y, 1000.0),
+            direction: -Vec3::Z,
+        }
+    }
+
+    #[test]
+    fn hits_every_layer_in_local_space() {
+        let (tile_map, to_world) = tile_map();
+        // 40 and 20 pixels in to the map in its local space.
+        let hits = layer_hits(ray_down(180.0, 90.0), &tile_map, to_world);
+
+        assert_eq!(
+            hits,
+            vec![
+                (UVec3::new(2, 1, 0), Vec3::new(40.0, 20.0, 0.0)),
+                (UVec3::new(2, 1, 1), Vec3::new(40.0, 20.0, 1.0)),
+                (UVec3::new(2, 1, 2), Vec3::new(40.0, 20.0, 2.0)),
+            ]
+        );
+        // Layer 2 is 2 units in front of the map, scaled to 4 in world space.
+        assert_eq!(
+            to_world.transform_point3(hits[2].1),
+            Vec3::new(180.0, 90.0, 14.0)
+        );
+    }
+
+    #[test]
+    fn slanted_rays_hit_each_layer_at_its_own_position() {
+        let (tile_map, to_world) = tile_map();
+        let ray = TileRay {
+            origin: Vec3::new(100.0, 50.0, 10.0 + 2.0 * 20.0),
+            direction: Vec3::new(1.0, 0.0, -1.0).normalize(),
+        };
+        let hits = layer_hits(ray, &tile_map, to_world);
+
+        // The ray moves 1 along x per unit along z, starting 20 units in front of layer 0.
+        assert_eq!(hits.len(), 3);
+        for ((_, local), x) in hits.iter().zip([20.0, 19.0, 18.0]) {
+            assert!((local.x - x).abs() < 1e-4);
+        }
+        assert_eq!(hits[2].0, UVec3::new(1, 0, 2));
+        assert_eq!(hits[1].0, UVec3::new(1, 0, 1));
+    }
+
+    #[test]
+    fn skips_hits_outside_of_the_map() {
+        let (tile_map, to_world) = tile_map();
+        // Left of and below the map.
+        assert!(layer_hits(ray_down(99.0, 90.0), &tile_map, to_world).is_empty());
+        assert!(layer_hits(ray_down(180.0, 49.0), &tile_map, to_world).is_empty());
+        // The map is 64 pixels wide, so 128 in world space.
+        assert!(layer_hits(ray_down(228.0, 90.0), &tile_map, to_world).is_empty());
+        assert_eq!(
+            layer_hits(ray_down(227.0, 90.0), &tile_map, to_world)[0].0,
+            UVec3::new(3, 1, 0)
+        );
+    }
+
+    #[test]
+    fn skips_layers_behind_the_ray_and_parallel_rays() {
+        let (tile_map, to_world) = tile_map();
+        let ray = TileRay {
+            // Between layer 0 and 1.
+            origin: Vec3::new(180.0, 90.0, 11.0),
+            direction: -Vec3::Z,
+        };
+        let hits = layer_hits(ray, &tile_map, to_world);
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].0.z, 0);
+
+        let ray = TileRay {
+            origin: Vec3::new(180.0, 90.0, 1000.0),
+            direction: Vec3::X,
+        };
+        assert!(layer_hits(ray, &tile_map, to_world).is_empty());
+    }
+}
@@ -0,0 +1,144 @@
+use bevy::prelude::*;
+
+use super::{TileHit, TileMapRayCastSource};
+use crate::TileMap;
+
+/// Add this next to a `TileMapRayCastSource` to pick tiles with the keyboard or a gamepad instead
+/// of the mouse. The arrow keys and the d-pad move the cursor by one tile on its layer, enter,
+/// space and the south face button activate the tile like `MouseButton::Left`.
+#[derive(Debug, Clone, Component)]
+pub struct TileCursor {
+    pub tile_map: Entity,
+    pub coord: UVec3,
+    /// Give every cursor its own input for one cursor per player.
+    pub input: TileCursorInput,
+}
+
+impl TileCursor {
+    pub fn new(tile_map: Entity, coord: UVec3) -> Self {
+        TileCursor {
+            tile_map,
+            coord,
+            input: TileCursorInput::default(),
+        }
+    }
+
+    pub fn with_input(mut self, input: TileCursorInput) -> Self {
+        self.input = input;
+        self
+    }
+}
+
+/// Which input moves a `TileCursor`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum TileCursorInput {
+    #[default]
+    /// The keyboard and every gamepad.
+    Any,
+    Keyboard,
+    Gamepad(Gamepad),
+}
+
+impl TileCursorInput {
+    /// The step the cursor moves by and whether the tile is activated.
+    fn read(
+        &self,
+        keyboard_input: &Input<KeyCode>,
+        gamepad_input: &Input<GamepadButton>,
+    ) -> (IVec2, bool) {
+        let mut step = IVec2::ZERO;
+        let mut activated = false;
+
+        if matches!(self, TileCursorInput::Any | TileCursorInput::Keyboard) {
+            activated |= keyboard_input.any_pressed([KeyCode::Return, KeyCode::Space]);
+            for (key, direction) in [
+                (KeyCode::Up, IVec2::Y),
+                (KeyCode::Down, -IVec2::Y),
+                (KeyCode::Left, -IVec2::X),
+                (KeyCode::Right, IVec2::X),
+            ] {
+                if keyboard_input.just_pressed(key) {
+                    step += direction;
+                }
+            }
+        }
+
+        let uses_gamepad = |gamepad: Gamepad| match self {
+            TileCursorInput::Any => true,
+            TileCursorInput::Keyboard => false,
+            TileCursorInput::Gamepad(cursor_gamepad) => *cursor_gamepad == gamepad,
+        };
+        for button in gamepad_input.get_just_pressed() {
+            if !uses_gamepad(button.0) {
+                continue;
+            }
+            step += match button.1 {
+                GamepadButtonType::DPadUp => IVec2::Y,
+                GamepadButtonType::DPadDown => -IVec2::Y,
+                GamepadButtonType::DPadLeft => -IVec2::X,
+                GamepadButtonType::DPadRight => IVec2::X,
+                _ => IVec2::ZERO,
+            };
+        }
+        activated |= gamepad_input
+            .get_pressed()
+            .any(|button| uses_gamepad(button.0) && button.1 == GamepadButtonType::South);
+
+        (step, activated)
+    }
+}
+
+pub(crate) fn update_tile_cursors(
+    keyboard_input: Res<Input<KeyCode>>,
+    gamepad_input: Res<Input<GamepadButton>>,
+    mut cursors: Query<(&mut TileCursor, &mut TileMapRayCastSource)>,
+    tile_maps: Query<(&TileMap, &GlobalTransform)>,
+) {
+    for (mut cursor, mut source) in cursors.iter_mut() {
+        let source = &mut *source;
+        let (step, activated) = cursor.input.read(&keyboard_input, &gamepad_input);
+        source.cursor = None;
+        source.ray = None;
+        source.hits.clear();
+
+        source.input.clear();
+        if activated {
+            source.input.press(MouseButton::Left);
+        } else {
+            source.input.release(MouseButton::Left);
+        }
+
+        let (tile_map, transform) = if let Ok(tile_map) = tile_maps.get(cursor.tile_map) {
+            tile_map
+        } else {
+            continue;
+        };
+        if tile_map.size.cmpeq(UVec3::ZERO).any() {
+            continue;
+        }
+
+        let max = tile_map.size - UVec3::ONE;
+        let coord = (cursor.coord.truncate().as_ivec2() + step)
+            .max(IVec2::ZERO)
+            .as_uvec2()
+            .extend(cursor.coord.z)
+            .min(max);
+        if coord != cursor.coord {
+            cursor.coord = coord;
+        }
+
+        if tile_map[coord].pickable {
+            let tile_position = tile_map.tile_size.as_vec2() / 2.0;
+            let local = ((coord.truncate() * tile_map.tile_size).as_vec2() + tile_position)
+                .extend(coord.z as f32);
+
+            source.hits.push(TileHit {
+                tile_map: cursor.tile_map,
+                coord,
+                position: transform.mul_vec3(local),
+                tile_position,
+                distance: 0.0,
+            });
+        }
+    }
+}
@@ -0,0 +1,55 @@
+use bevy::{prelude::*, utils::HashMap};
+
+use super::{TileMapRayCastSource, TileSelector};
+use crate::{TileHighlight, TileHighlights};
+
+/// Keeps the hover and selection highlights in the `TileHighlights` of the tile maps.
+#[allow(clippy::type_complexity)]
+pub(crate) fn update_interaction_highlights(
+    sources: Query<
+        (Entity, Option<&TileMapRayCastSource>, Option<&TileSelector>),
+        Or<(With<TileMapRayCastSource>, With<TileSelector>)>,
+    >,
+    mut tile_maps: Query<(Entity, &mut TileHighlights)>,
+) {
+    let mut wanted: HashMap<(Entity, Entity), HashMap<UVec3, TileHighlight>> = HashMap::default();
+    for (owner, source, selector) in sources.iter() {
+        if let Some((highlight, (tile_map, selection))) =
+            selector.and_then(|selector| Some((selector.highlight?, selector.shown()?)))
+        {
+            wanted
+                .entry((tile_map, owner))
+                .or_default()
+                .extend(selection.iter().map(|coord| (coord, highlight)));
+        }
+
+        // Drawn over the selection of the same entity.
+        if let Some((highlight, hovered)) =
+            source.and_then(|source| Some((source.hover_highlight?, source.hovered?)))
+        {
+            wanted
+                .entry((hovered.tile_map, owner))
+                .or_default()
+                .insert(hovered.coord, highlight);
+        }
+    }
+
+    for (tile_map, mut highlights) in tile_maps.iter_mut() {
+        let removed: Vec<Entity> = highlights
+            .generated_owners()
+            .filter(|owner| !wanted.contains_key(&(tile_map, *owner)))
+            .collect();
+        for owner in removed {
+            highlights.set_generated(owner, HashMap::default());
+        }
+
+        for ((_, owner), owner_highlights) in wanted
+            .iter()
+            .filter(|((wanted_map, _), _)| *wanted_map == tile_map)
+        {
+            if highlights.generated(*owner) != Some(owner_highlights) {
+                highlights.set_generated(*owner, owner_highlights.clone());
+            }
+        }
+    }
+}
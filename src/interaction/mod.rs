@@ -0,0 +1,509 @@
+#[cfg(not(feature = "raycast"))]
+mod analytic;
+mod cursor;
+mod highlight;
+#[cfg(feature = "raycast")]
+mod raycast;
+mod selector;
+
+use std::cmp::Ordering;
+
+use bevy::{prelude::*, render::camera::RenderTarget, utils::HashMap, window::WindowId};
+
+#[cfg(not(feature = "raycast"))]
+use analytic::cast_rays;
+pub use cursor::{TileCursor, TileCursorInput};
+#[cfg(feature = "raycast")]
+use raycast::cast_rays;
+pub use selector::{TileSelectMode, TileSelector};
+
+use crate::{render::TIME_WRAP_SECONDS, TileHighlight, TileKind, TileMap, TileSheet};
+
+/// Mouse and touch picking of tiles with `TileMapRayCastSource`s, see
+/// `TileMapInteractionEvent`. Tiles are hit tested directly against the tile maps, or against a
+/// plane mesh per chunk with `bevy_mod_raycast` if the `raycast` feature is enabled.
+pub struct TileMapInteractionPlugin;
+
+impl Plugin for TileMapInteractionPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<TileMapInteractionEvent>()
+            .add_system_to_stage(CoreStage::PreUpdate, update_camera_ray)
+            .add_system_to_stage(
+                CoreStage::PreUpdate,
+                queue_interaction_events.after(update_camera_ray),
+            );
+
+        app.add_system_to_stage(CoreStage::PreUpdate, cast_rays.after(update_camera_ray))
+            .add_system_to_stage(
+                CoreStage::PreUpdate,
+                cursor::update_tile_cursors.after(cast_rays),
+            )
+            .add_system_to_stage(
+                CoreStage::PreUpdate,
+                sort_hits
+                    .after(cursor::update_tile_cursors)
+                    .before(queue_interaction_events),
+            )
+            .add_system_to_stage(
+                CoreStage::PreUpdate,
+                selector::update_tile_selectors.after(queue_interaction_events),
+            )
+            .add_system_to_stage(
+                CoreStage::PreUpdate,
+                highlight::update_interaction_highlights.after(selector::update_tile_selectors),
+            );
+
+        // Runs after the chunks spawned in `PostUpdate` exist.
+        #[cfg(feature = "raycast")]
+        app.add_system_to_stage(CoreStage::Last, raycast::update_chunk_meshes);
+    }
+}
+
+/// A ray in world space.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TileRay {
+    pub origin: Vec3,
+    /// Normalized.
+    pub direction: Vec3,
+}
+
+/// A pickable tile under the cursor.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TileHit {
+    pub tile_map: Entity,
+    pub coord: UVec3,
+    /// Where the ray hit the tile, in world space.
+    pub position: Vec3,
+    /// Where the ray hit the tile, in pixels from its bottom left corner.
+    pub tile_position: Vec2,
+    /// From the origin of the ray.
+    pub distance: f32,
+}
+
+/// Which of the tiles under the cursor end up in `TileMapRayCastSource::hits`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum TilePickMode {
+    #[default]
+    /// Only the tile on the highest pickable layer of the closest tile map.
+    Topmost,
+    /// Every pickable tile.
+    All,
+}
+
+/// Add this to a camera to pick the tiles under the cursor in the window the camera renders to.
+/// Every source picks on its own, so there can be one per viewport or window.
+#[derive(Debug, Default, Clone, Component)]
+pub struct TileMapRayCastSource {
+    camera: Option<Entity>,
+    pick_mode: TilePickMode,
+    pixel_perfect: bool,
+    hover_highlight: Option<TileHighlight>,
+    cursor: Option<Vec2>,
+    ray: Option<TileRay>,
+    hits: Vec<TileHit>,
+    hovered: Option<TileHit>,
+    // The buttons of this source. Touches press `MouseButton::Left`.
+    input: Input<MouseButton>,
+    // Whether `MouseButton::Left` in `input` was pressed by a touch.
+    touch_pressed: bool,
+    buttons: HashMap<MouseButton, ButtonState>,
+    last_clicks: HashMap<MouseButton, (f64, Entity, UVec3)>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct ButtonState {
+    pressed: TileHit,
+    dragged: Option<TileHit>,
+}
+
+impl TileMapRayCastSource {
+    /// A source on another entity than the camera it picks through.
+    pub fn with_camera(camera: Entity) -> Self {
+        TileMapRayCastSource {
+            camera: Some(camera),
+            ..Default::default()
+        }
+    }
+
+    pub fn with_pick_mode(mut self, pick_mode: TilePickMode) -> Self {
+        self.pick_mode = pick_mode;
+        self
+    }
+
+    /// Only hit tiles where the pixel under the cursor isn't transparent, after applying the
+    /// `TileTransform` of the tile.
+    pub fn with_pixel_perfect(mut self, pixel_perfect: bool) -> Self {
+        self.pixel_perfect = pixel_perfect;
+        self
+    }
+
+    /// Highlights the tile under the cursor. Needs `TileHighlights` on the tile map.
+    pub fn with_hover_highlight(mut self, highlight: TileHighlight) -> Self {
+        self.hover_highlight = Some(highlight);
+        self
+    }
+
+    /// The ray through the cursor, `None` while the cursor is outside of the window.
+    #[inline]
+    pub fn ray(&self) -> Option<TileRay> {
+        self.ray
+    }
+
+    /// The pickable tiles on the ray, see `TilePickMode`. Ordered by tile map, the closest first,
+    /// and then by layer, the highest first.
+    #[inline]
+    pub fn hits(&self) -> &[TileHit] {
+        &self.hits
+    }
+
+    /// The tile hit on `layer` of `tile_map`. Needs `TilePickMode::All` for layers below the
+    /// topmost hit.
+    pub fn hit_on_layer(&self, tile_map: Entity, layer: u32) -> Option<&TileHit> {
+        self.hits
+            .iter()
+            .find(|hit| hit.tile_map == tile_map && hit.coord.z == layer)
+    }
+
+    /// The tile under the cursor.
+    #[inline]
+    pub fn hovered(&self) -> Option<&TileHit> {
+        self.hovered.as_ref()
+    }
+}
+
+pub fn update_camera_ray(
+    windows: Res<Windows>,
+    images: Res<Assets<Image>>,
+    mouse_button_input: Res<Input<MouseButton>>,
+    touches: Res<Touches>,
+    mut ray_sources: Query<(Entity, &mut TileMapRayCastSource), Without<TileCursor>>,
+    cameras: Query<(&Camera, &GlobalTransform)>,
+) {
+    for (entity, mut source) in ray_sources.iter_mut() {
+        let source = &mut *source;
+        let camera = cameras.get(source.camera.unwrap_or(entity)).ok();
+        let window = camera.and_then(|(camera, _)| match &camera.target {
+            RenderTarget::Window(window_id) => windows.get(*window_id),
+            RenderTarget::Image(_) => None,
+        });
+
+        source.input.clear();
+        for button in mouse_button_input.get_just_pressed() {
+            source.input.press(*button);
+        }
+        for button in mouse_button_input.get_just_released() {
+            source.input.release(*button);
+        }
+
+        // Touches are only reported for the primary window. A touch that just ended still
+        // places the cursor, so it can release on the tile it was on.
+        let touch = window
+            .filter(|window| window.id() == WindowId::primary())
+            .and_then(|_| touches.iter().chain(touches.iter_just_released()).next());
+        if touch.is_some()
+            && touches.iter_just_pressed().next().is_some()
+            && !source.input.pressed(MouseButton::Left)
+        {
+            source.input.press(MouseButton::Left);
+            source.touch_pressed = true;
+        }
+        // Only a press from a touch is released by it, a held mouse button stays pressed.
+        if source.touch_pressed && touches.iter().next().is_none() {
+            source.input.release(MouseButton::Left);
+            source.touch_pressed = false;
+        }
+
+        source.cursor = touch
+            .map(|touch| touch.position())
+            .or_else(|| window.and_then(|window| window.cursor_position()));
+        source.ray = source
+            .cursor
+            .zip(camera)
+            .and_then(|(cursor, (camera, camera_transform))| {
+                let screen_size = camera.target.get_logical_size(&windows, &images)?;
+                Some(cursor_ray(cursor, screen_size, camera, camera_transform))
+            });
+    }
+}
+
+fn cursor_ray(
+    cursor: Vec2,
+    screen_size: Vec2,
+    camera: &Camera,
+    camera_transform: &GlobalTransform,
+) -> TileRay {
+    let cursor_ndc = cursor / screen_size * 2.0 - Vec2::ONE;
+    let ndc_to_world = camera_transform.compute_matrix() * camera.projection_matrix.inverse();
+
+    // Bevy uses a reversed depth, so 1.0 is the near plane.
+    let near = ndc_to_world.project_point3(cursor_ndc.extend(1.0));
+    let far = ndc_to_world.project_point3(cursor_ndc.extend(0.5));
+
+    TileRay {
+        origin: near,
+        direction: (far - near).normalize(),
+    }
+}
+
+/// Drops the hits on unpickable layers and transparent pixels, orders the rest and applies the
+/// `TilePickMode`.
+pub(crate) fn sort_hits(
+    time: Res<Time>,
+    tile_sheets: Res<Assets<TileSheet>>,
+    mut ray_sources: Query<&mut TileMapRayCastSource>,
+    tile_maps: Query<&TileMap>,
+) {
+    let time = (time.seconds_since_startup() % TIME_WRAP_SECONDS) as f32;
+
+    for mut source in ray_sources.iter_mut() {
+        let source = &mut *source;
+        let pixel_perfect = source.pixel_perfect;
+        source.hits.retain(|hit| {
+            let tile_map = if let Ok(tile_map) = tile_maps.get(hit.tile_map) {
+                tile_map
+            } else {
+                return false;
+            };
+
+            tile_map.is_layer_pickable(hit.coord.z)
+                && (!pixel_perfect
+                    || is_opaque(hit, tile_map, tile_sheets.get(&tile_map.tile_sheet), time))
+        });
+
+        let mut map_distances: HashMap<Entity, f32> = HashMap::default();
+        for hit in source.hits.iter() {
+            let distance = map_distances.entry(hit.tile_map).or_insert(hit.distance);
+            *distance = distance.min(hit.distance);
+        }
+
+        source.hits.sort_by(|a, b| {
+            map_distances[&a.tile_map]
+                .partial_cmp(&map_distances[&b.tile_map])
+                .unwrap_or(Ordering::Equal)
+                .then(a.tile_map.cmp(&b.tile_map))
+                .then(b.coord.z.cmp(&a.coord.z))
+        });
+
+        if source.pick_mode == TilePickMode::Topmost {
+            source.hits.truncate(1);
+        }
+    }
+}
+
+/// Whether the pixel of the tile under `hit` is drawn with any alpha. Matches what `chunk.wgsl`
+/// does.
+fn is_opaque(hit: &TileHit, tile_map: &TileMap, tile_sheet: Option<&TileSheet>, time: f32) -> bool {
+    let (transform, mask_color, idx) = match &tile_map[hit.coord].kind {
+        Some(TileKind::Color { color, transform }) => (transform, color, None),
+        Some(TileKind::Sprite {
+            idx,
+            transform,
+            mask_color,
+        }) => (transform, mask_color, Some(*idx)),
+        Some(TileKind::AnimatedSprite {
+            animation,
+            transform,
+            mask_color,
+        }) => {
+            let idx = tile_sheet
+                .and_then(|tile_sheet| tile_sheet.animation(*animation))
+                .map(|animation| animation.frame_at(time));
+            (transform, mask_color, idx)
+        }
+        None => return false,
+    };
+
+    let matrix: Mat3 = transform.into();
+    let uv = transform
+        .flip
+        .apply(matrix.transform_point2(hit.tile_position / tile_map.tile_size.as_vec2()));
+    if uv.min_element() < -0.01 || uv.max_element() > 1.01 {
+        return false;
+    }
+
+    let alpha = match idx {
+        Some(idx) => tile_sheet
+            .and_then(|tile_sheet| tile_sheet.alpha_at(idx, uv))
+            .unwrap_or(1.0),
+        None => 1.0,
+    };
+    alpha * mask_color.a() > 0.0
+}
+
+/// The longest time between two clicks on the same tile that still counts as a double click.
+pub const DOUBLE_CLICK_TIME: f64 = 0.3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TileMapInteractionKind {
+    JustEntered,
+    Hovering,
+    JustExited,
+    Pressed(MouseButton),
+    /// Sent on the hovered tile after any `Clicked`, `DoubleClicked` or `DragEnded`. If the
+    /// button was pressed on a tile and released off the tile maps, it's sent on the last tile
+    /// the cursor was dragged over, or else the tile the button was pressed on.
+    Released(MouseButton),
+    /// The button was pressed and released on the same tile without dragging.
+    Clicked(MouseButton),
+    /// Sent after the second `Clicked` within `DOUBLE_CLICK_TIME`.
+    DoubleClicked(MouseButton),
+    /// The button was pressed on this tile and the cursor then moved to another tile.
+    DragStarted(MouseButton),
+    /// The cursor moved on to this tile while dragging, including the first tile after
+    /// `DragStarted`.
+    DragOver(MouseButton),
+    /// The button was released while dragging, on this tile or after leaving it for no tile.
+    DragEnded(MouseButton),
+}
+
+/// The keyboard modifiers that were held during an interaction.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct TileMapModifiers {
+    pub shift: bool,
+    pub control: bool,
+    pub alt: bool,
+    pub logo: bool,
+}
+
+impl TileMapModifiers {
+    fn from_input(input: &Input<KeyCode>) -> Self {
+        TileMapModifiers {
+            shift: input.any_pressed([KeyCode::LShift, KeyCode::RShift]),
+            control: input.any_pressed([KeyCode::LControl, KeyCode::RControl]),
+            alt: input.any_pressed([KeyCode::LAlt, KeyCode::RAlt]),
+            logo: input.any_pressed([KeyCode::LWin, KeyCode::RWin]),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TileMapInteractionEvent {
+    /// The entity with the `TileMapRayCastSource`.
+    pub source: Entity,
+    /// The camera the source picks through, or the source for a `TileCursor`.
+    pub camera: Entity,
+    pub tile_map: Entity,
+    pub coord: UVec3,
+    /// Where the tile was hit in world space. Events sent while the cursor isn't on the tile
+    /// use the last hit.
+    pub position: Vec3,
+    /// Where the tile was hit, in pixels from its bottom left corner.
+    pub tile_position: Vec2,
+    pub modifiers: TileMapModifiers,
+    pub kind: TileMapInteractionKind,
+}
+
+pub fn queue_interaction_events(
+    time: Res<Time>,
+    keyboard_input: Res<Input<KeyCode>>,
+    mut interaction_writer: EventWriter<TileMapInteractionEvent>,
+    mut ray_sources: Query<(Entity, &mut TileMapRayCastSource)>,
+) {
+    let now = time.seconds_since_startup();
+    let modifiers = TileMapModifiers::from_input(&keyboard_input);
+
+    for (entity, mut source) in ray_sources.iter_mut() {
+        let source = &mut *source;
+        let camera = source.camera.unwrap_or(entity);
+        let mut send = |hit: TileHit, kind| {
+            interaction_writer.send(TileMapInteractionEvent {
+                source: entity,
+                camera,
+                tile_map: hit.tile_map,
+                coord: hit.coord,
+                position: hit.position,
+                tile_position: hit.tile_position,
+                modifiers,
+                kind,
+            });
+        };
+        let same_tile = |a: &TileHit, b: &TileHit| a.tile_map == b.tile_map && a.coord == b.coord;
+
+        let last_hovered = source.hovered;
+        let new_hovered = source.hits.first().copied();
+
+        match (last_hovered, new_hovered) {
+            (Some(last), Some(new)) if same_tile(&last, &new) => {
+                send(new, TileMapInteractionKind::Hovering)
+            }
+            (last, new) => {
+                if let Some(last) = last {
+                    send(last, TileMapInteractionKind::JustExited);
+                }
+                if let Some(new) = new {
+                    send(new, TileMapInteractionKind::JustEntered);
+                }
+            }
+        }
+
+        if let Some(hovered) = new_hovered {
+            for button in source.input.get_just_pressed() {
+                send(hovered, TileMapInteractionKind::Pressed(*button));
+                source.buttons.insert(
+                    *button,
+                    ButtonState {
+                        pressed: hovered,
+                        dragged: None,
+                    },
+                );
+            }
+
+            for (button, state) in source.buttons.iter_mut() {
+                let last = state.dragged.as_ref().unwrap_or(&state.pressed);
+                if !source.input.pressed(*button) || same_tile(last, &hovered) {
+                    continue;
+                }
+
+                if state.dragged.is_none() {
+                    send(state.pressed, TileMapInteractionKind::DragStarted(*button));
+                }
+                send(hovered, TileMapInteractionKind::DragOver(*button));
+                state.dragged = Some(hovered);
+            }
+        }
+
+        for button in source.input.get_just_released() {
+            let state = if let Some(state) = source.buttons.remove(button) {
+                state
+            } else {
+                if let Some(hovered) = new_hovered {
+                    send(hovered, TileMapInteractionKind::Released(*button));
+                }
+                continue;
+            };
+
+            if let Some(dragged) = state.dragged {
+                send(
+                    new_hovered.unwrap_or(dragged),
+                    TileMapInteractionKind::DragEnded(*button),
+                );
+            } else if let Some(hovered) = new_hovered.filter(|hit| same_tile(hit, &state.pressed)) {
+                send(hovered, TileMapInteractionKind::Clicked(*button));
+
+                let double_clicked = matches!(
+                    source.last_clicks.get(button),
+                    Some((time, tile_map, coord))
+                        if now - *time <= DOUBLE_CLICK_TIME
+                            && *tile_map == hovered.tile_map
+                            && *coord == hovered.coord
+                );
+                if double_clicked {
+                    send(hovered, TileMapInteractionKind::DoubleClicked(*button));
+                    source.last_clicks.remove(button);
+                } else {
+                    source
+                        .last_clicks
+                        .insert(*button, (now, hovered.tile_map, hovered.coord));
+                }
+            }
+
+            // Every press ends with a release, even if the button was released off the tile maps.
+            send(
+                new_hovered.or(state.dragged).unwrap_or(state.pressed),
+                TileMapInteractionKind::Released(*button),
+            );
+        }
+
+        source.hovered = new_hovered;
+    }
+}
@@ -0,0 +1,123 @@
+use bevy::{
+    prelude::*,
+    render::{
+        mesh::{Indices, PrimitiveTopology},
+        primitives::Aabb,
+    },
+    utils::HashMap,
+};
+use bevy_mod_raycast::*;
+
+use super::{TileHit, TileMapRayCastSource};
+use crate::chunk::ChunkData;
+
+pub struct TileMapRayCast;
+
+pub type TileMapRayCastMesh = RayCastMesh<TileMapRayCast>;
+
+/// Gives every chunk a plane mesh to ray cast against.
+pub(crate) fn update_chunk_meshes(
+    mut mesh_cache: Local<HashMap<UVec2, Handle<Mesh>>>,
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    chunks: Query<(Entity, &ChunkData), Changed<ChunkData>>,
+) {
+    for (entity, chunk_data) in chunks.iter() {
+        let screen_chunk_size = chunk_data.chunk_size * chunk_data.tile_size;
+        let mesh = mesh_cache
+            .entry(screen_chunk_size)
+            .or_insert_with(|| meshes.add(plane_mesh(screen_chunk_size.as_vec2())));
+
+        commands
+            .entity(entity)
+            .insert_bundle((mesh.as_weak::<Mesh>(), TileMapRayCastMesh::default()));
+    }
+}
+
+/// Intersects the rays with the plane meshes of the chunks.
+#[allow(clippy::type_complexity)]
+pub(crate) fn cast_rays(
+    meshes: Res<Assets<Mesh>>,
+    mut sources: Query<&mut TileMapRayCastSource>,
+    chunks: Query<
+        (&Handle<Mesh>, &Aabb, &GlobalTransform, &ChunkData, &Parent),
+        With<TileMapRayCastMesh>,
+    >,
+) {
+    for mut source in sources.iter_mut() {
+        let source = &mut *source;
+        source.hits.clear();
+
+        let ray = if let Some(ray) = source.ray {
+            Ray3d::new(ray.origin, ray.direction)
+        } else {
+            continue;
+        };
+
+        for (mesh, aabb, transform, chunk_data, tile_map_entity) in chunks.iter() {
+            let mesh_to_world = transform.compute_matrix();
+            if ray.intersects_aabb(aabb, &mesh_to_world).is_none() {
+                continue;
+            }
+
+            let intersection = if let Some(intersection) = meshes
+                .get(mesh)
+                .and_then(|mesh| ray_intersection_over_mesh(mesh, &mesh_to_world, &ray))
+            {
+                intersection
+            } else {
+                continue;
+            };
+
+            let position = intersection.position() - transform.translation;
+            let chunk_tile_coord = (position.truncate() / chunk_data.tile_size.as_vec2())
+                .as_uvec2()
+                .min(chunk_data.chunk_size - UVec2::ONE);
+
+            if chunk_data.tiles
+                [(chunk_tile_coord.y * chunk_data.chunk_size.x + chunk_tile_coord.x) as usize]
+                .pickable
+            {
+                let coord = (chunk_tile_coord
+                    + chunk_data.chunk_size * chunk_data.chunk_coord.0.truncate())
+                .extend(chunk_data.chunk_coord.0.z);
+
+                source.hits.push(TileHit {
+                    tile_map: tile_map_entity.0,
+                    coord,
+                    position: intersection.position(),
+                    tile_position: position.truncate()
+                        - (chunk_tile_coord * chunk_data.tile_size).as_vec2(),
+                    distance: intersection.distance(),
+                });
+            }
+        }
+    }
+}
+
+pub fn plane_mesh(size: Vec2) -> Mesh {
+    let vertices = [
+        ([0.0, 0.0, 0.0], [0.0, 0.0, 1.0], [0.0, 0.0]),
+        ([0.0, size.y, 0.0], [0.0, 0.0, 1.0], [0.0, 1.0]),
+        ([size.x, size.y, 0.0], [0.0, 0.0, 1.0], [1.0, 1.0]),
+        ([size.x, 0.0, 0.0], [0.0, 0.0, 1.0], [1.0, 0.0]),
+    ];
+
+    let indices = Indices::U16(vec![0, 2, 1, 0, 3, 2]);
+
+    let mut positions = Vec::new();
+    let mut normals = Vec::new();
+    let mut uvs = Vec::new();
+    for (position, normal, uv) in &vertices {
+        positions.push(*position);
+        normals.push(*normal);
+        uvs.push(*uv);
+    }
+
+    let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
+    mesh.set_indices(Some(indices));
+    mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, positions);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, normals);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, uvs);
+    mesh
+}
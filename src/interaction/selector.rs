@@ -0,0 +1,161 @@
+use bevy::prelude::*;
+
+use super::{TileMapInteractionEvent, TileMapInteractionKind, TileMapModifiers};
+use crate::{selection::TileSelection, TileHighlight};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TileSelectMode {
+    /// Selects the box between the tile the drag started on and the tile under the cursor.
+    Box,
+    /// Selects the tiles inside of the path the cursor was dragged along.
+    Lasso,
+}
+
+/// Add this next to a `TileMapRayCastSource` to select tiles by clicking or dragging with
+/// `button`. A new selection replaces the old one, holding shift adds to it, control removes
+/// from it and both keep only the tiles in both. Selections stay on the layer the drag started on.
+#[derive(Debug, Clone, Component)]
+pub struct TileSelector {
+    pub mode: TileSelectMode,
+    pub button: MouseButton,
+    /// Drawn over the selection, and the preview while dragging. Needs `TileHighlights` on the
+    /// tile map.
+    pub highlight: Option<TileHighlight>,
+    tile_map: Option<Entity>,
+    selection: TileSelection,
+    drag: Option<SelectorDrag>,
+}
+
+#[derive(Debug, Clone)]
+struct SelectorDrag {
+    tile_map: Entity,
+    path: Vec<UVec3>,
+    modifiers: TileMapModifiers,
+    preview: TileSelection,
+}
+
+impl TileSelector {
+    pub fn new(mode: TileSelectMode) -> Self {
+        TileSelector {
+            mode,
+            button: MouseButton::Left,
+            highlight: None,
+            tile_map: None,
+            selection: TileSelection::new(),
+            drag: None,
+        }
+    }
+
+    /// The tile map of the selection.
+    #[inline]
+    pub fn tile_map(&self) -> Option<Entity> {
+        self.tile_map
+    }
+
+    #[inline]
+    pub fn selection(&self) -> &TileSelection {
+        &self.selection
+    }
+
+    #[inline]
+    pub fn selection_mut(&mut self) -> &mut TileSelection {
+        &mut self.selection
+    }
+
+    /// What the selection would be if the current drag ended now.
+    #[inline]
+    pub fn preview(&self) -> Option<&TileSelection> {
+        self.drag.as_ref().map(|drag| &drag.preview)
+    }
+
+    /// The preview while dragging, otherwise the selection, and its tile map.
+    pub(crate) fn shown(&self) -> Option<(Entity, &TileSelection)> {
+        match &self.drag {
+            Some(drag) => Some((drag.tile_map, &drag.preview)),
+            None => Some((self.tile_map?, &self.selection)),
+        }
+    }
+
+    fn update_preview(&mut self) {
+        let drag = if let Some(drag) = self.drag.as_mut() {
+            drag
+        } else {
+            return;
+        };
+
+        let layer = drag.path[0].z;
+        let dragged = match self.mode {
+            TileSelectMode::Box => {
+                let last = *drag.path.last().unwrap();
+                TileSelection::from_box(drag.path[0], last.truncate().extend(layer))
+            }
+            TileSelectMode::Lasso => {
+                let points: Vec<UVec2> = drag.path.iter().map(|coord| coord.truncate()).collect();
+                TileSelection::from_lasso(&points, layer)
+            }
+        };
+
+        drag.preview = if self.tile_map != Some(drag.tile_map) {
+            dragged
+        } else {
+            match (drag.modifiers.shift, drag.modifiers.control) {
+                (false, false) => dragged,
+                (true, false) => self.selection.union(&dragged),
+                (false, true) => self.selection.difference(&dragged),
+                (true, true) => self.selection.intersection(&dragged),
+            }
+        };
+    }
+}
+
+pub(crate) fn update_tile_selectors(
+    mut interaction_events: EventReader<TileMapInteractionEvent>,
+    mut selectors: Query<&mut TileSelector>,
+) {
+    for event in interaction_events.iter() {
+        let mut selector = if let Ok(selector) = selectors.get_mut(event.source) {
+            selector
+        } else {
+            continue;
+        };
+        let button = selector.button;
+
+        match event.kind {
+            TileMapInteractionKind::Pressed(pressed) if pressed == button => {
+                selector.drag = Some(SelectorDrag {
+                    tile_map: event.tile_map,
+                    path: vec![event.coord],
+                    modifiers: event.modifiers,
+                    preview: TileSelection::new(),
+                });
+            }
+            TileMapInteractionKind::DragOver(dragged) if dragged == button => {
+                if let Some(drag) = selector.drag.as_mut() {
+                    if drag.tile_map != event.tile_map {
+                        continue;
+                    }
+                    drag.path.push(event.coord);
+                }
+            }
+            TileMapInteractionKind::Clicked(released)
+            | TileMapInteractionKind::DragEnded(released)
+                if released == button =>
+            {
+                selector.update_preview();
+                if let Some(drag) = selector.drag.take() {
+                    selector.tile_map = Some(drag.tile_map);
+                    selector.selection = drag.preview;
+                }
+                continue;
+            }
+            // Released off the tile maps without dragging, there is nothing to select.
+            TileMapInteractionKind::Released(released) if released == button => {
+                selector.drag = None;
+                continue;
+            }
+            _ => continue,
+        }
+
+        selector.update_preview();
+    }
+}
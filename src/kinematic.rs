@@ -0,0 +1,509 @@
+use bevy::prelude::*;
+
+use crate::{Tile, TileMap};
+
+/// How a tile interacts with a `KinematicTileBody`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TileCollision {
+    Empty,
+    Solid,
+    /// Only blocks bodies that land on it from above.
+    OneWay,
+    /// A 45° floor that goes up from the bottom left corner to the top right corner of the tile.
+    SlopeUpRight,
+    /// A 45° floor that goes up from the bottom right corner to the top left corner of the tile.
+    SlopeUpLeft,
+}
+
+impl TileCollision {
+    #[inline]
+    fn is_slope(&self) -> bool {
+        matches!(
+            self,
+            TileCollision::SlopeUpRight | TileCollision::SlopeUpLeft
+        )
+    }
+
+    /// The height of the floor at `x` in `0.0..=1.0` of the tile width, as a fraction of the tile
+    /// height.
+    #[inline]
+    fn floor_height(&self, x: f32) -> f32 {
+        match self {
+            TileCollision::SlopeUpRight => x.clamp(0.0, 1.0),
+            TileCollision::SlopeUpLeft => 1.0 - x.clamp(0.0, 1.0),
+            _ => 1.0,
+        }
+    }
+}
+
+/// Add this next to a `TileMap` to let `KinematicTileBody`s collide with the tiles on `layer`.
+#[derive(Component)]
+pub struct TileMapCollision {
+    pub layer: u32,
+    classify: Box<dyn Fn(&Tile) -> TileCollision + Send + Sync>,
+}
+
+impl TileMapCollision {
+    pub fn new(
+        layer: u32,
+        classify: impl Fn(&Tile) -> TileCollision + Send + Sync + 'static,
+    ) -> Self {
+        TileMapCollision {
+            layer,
+            classify: Box::new(classify),
+        }
+    }
+
+    fn collision_at(&self, tile_map: &TileMap, coord: IVec2) -> TileCollision {
+        if coord.x < 0
+            || coord.y < 0
+            || coord.x >= tile_map.size.x as i32
+            || coord.y >= tile_map.size.y as i32
+        {
+            return TileCollision::Empty;
+        }
+
+        tile_map
+            .get(coord.as_uvec2().extend(self.layer))
+            .map(|tile| (self.classify)(tile))
+            .unwrap_or(TileCollision::Empty)
+    }
+}
+
+/// An axis aligned box that is moved by `velocity` every frame and stopped by the tiles of
+/// `tile_map`. `size` and `velocity` are in the local space of the tile map and the box is
+/// centered on the `Transform` of the entity. The entity is expected to not have a parent.
+#[derive(Debug, Clone, Component)]
+pub struct KinematicTileBody {
+    pub tile_map: Entity,
+    pub size: Vec2,
+    pub velocity: Vec2,
+    /// Set when the body is standing on a solid tile, a one way platform or a slope.
+    pub on_ground: bool,
+}
+
+impl KinematicTileBody {
+    pub fn new(tile_map: Entity, size: Vec2) -> Self {
+        KinematicTileBody {
+            tile_map,
+            size,
+            velocity: Vec2::ZERO,
+            on_ground: false,
+        }
+    }
+}
+
+/// Sent for every tile a `KinematicTileBody` got stopped by. `normal` points away from the tile.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TileContactEvent {
+    pub entity: Entity,
+    pub tile_map: Entity,
+    pub coord: UVec3,
+    pub normal: Vec2,
+}
+
+// Keeps bodies from ending up exactly on a tile border, where flooring the position would put them
+// inside the tile they just got pushed out of.
+const SKIN: f32 = 0.001;
+
+pub(crate) fn move_kinematic_bodies(
+    time: Res<Time>,
+    mut contact_writer: EventWriter<TileContactEvent>,
+    tile_maps: Query<(&TileMap, &TileMapCollision, &GlobalTransform)>,
+    mut bodies: Query<(Entity, &mut KinematicTileBody, &mut Transform)>,
+) {
+    let delta_seconds = time.delta_seconds();
+
+    for (entity, mut body, mut transform) in bodies.iter_mut() {
+        let (tile_map, collision, map_transform) =
+            if let Ok(tile_map) = tile_maps.get(body.tile_map) {
+                tile_map
+            } else {
+                continue;
+            };
+
+        let to_local = map_transform.compute_matrix().inverse();
+        let local = to_local.transform_point3(transform.translation);
+
+        let mut sweep = Sweep {
+            tile_map,
+            collision,
+            tile_size: tile_map.tile_size.as_vec2(),
+            half_size: body.size / 2.0,
+            center: local.truncate(),
+            velocity: body.velocity,
+            contacts: Vec::new(),
+        };
+
+        let was_on_ground = body.on_ground;
+        let delta = sweep.velocity * delta_seconds;
+        sweep.move_x(delta.x);
+        let on_ground = sweep.move_y(delta.y) | sweep.resolve_slopes(was_on_ground, delta.x);
+
+        body.velocity = sweep.velocity;
+        body.on_ground = on_ground;
+
+        transform.translation = map_transform
+            .compute_matrix()
+            .transform_point3(sweep.center.extend(local.z));
+
+        for (coord, normal) in sweep.contacts {
+            contact_writer.send(TileContactEvent {
+                entity,
+                tile_map: body.tile_map,
+                coord: coord.as_uvec2().extend(collision.layer),
+                normal,
+            });
+        }
+    }
+}
+
+struct Sweep<'a> {
+    tile_map: &'a TileMap,
+    collision: &'a TileMapCollision,
+    tile_size: Vec2,
+    half_size: Vec2,
+    center: Vec2,
+    velocity: Vec2,
+    contacts: Vec<(IVec2, Vec2)>,
+}
+
+impl<'a> Sweep<'a> {
+    fn tile_range(&self, min: f32, max: f32, tile_size: f32) -> std::ops::RangeInclusive<i32> {
+        ((min + SKIN) / tile_size).floor() as i32..=((max - SKIN) / tile_size).floor() as i32
+    }
+
+    /// The slope the body is standing on.
+    fn slope_under(&self) -> Option<(IVec2, TileCollision)> {
+        let bottom = self.center.y - self.half_size.y;
+        let column = (self.center.x / self.tile_size.x).floor() as i32;
+
+        [bottom + SKIN, bottom - SKIN].iter().find_map(|y| {
+            let coord = IVec2::new(column, (y / self.tile_size.y).floor() as i32);
+            let collision = self.collision.collision_at(self.tile_map, coord);
+            if collision.is_slope() {
+                Some((coord, collision))
+            } else {
+                None
+            }
+        })
+    }
+
+    fn move_x(&mut self, delta: f32) {
+        if delta == 0.0 {
+            return;
+        }
+
+        let rows = self.tile_range(
+            self.center.y - self.half_size.y,
+            self.center.y + self.half_size.y,
+            self.tile_size.y,
+        );
+        // The tile a slope leads up to is usually solid up to the height of the slope, so bodies
+        // walking up a slope step on to it.
+        let step = self.slope_under().and_then(|(coord, collision)| {
+            let edge_height = collision.floor_height(if delta > 0.0 { 1.0 } else { 0.0 });
+            (edge_height == 1.0 && coord.y == *rows.start())
+                .then(|| IVec2::new(coord.x + delta.signum() as i32, coord.y))
+        });
+        let edge = self.center.x + self.half_size.x * delta.signum();
+        let target = edge + delta;
+
+        let columns: Vec<i32> = if delta > 0.0 {
+            let first = ((edge - SKIN) / self.tile_size.x).floor() as i32 + 1;
+            let last = ((target - SKIN) / self.tile_size.x).floor() as i32;
+            (first..=last).collect()
+        } else {
+            let first = ((edge + SKIN) / self.tile_size.x).floor() as i32 - 1;
+            let last = (target / self.tile_size.x).floor() as i32;
+            (last..=first).rev().collect()
+        };
+
+        for column in columns {
+            let blocking: Vec<i32> = rows
+                .clone()
+                .filter(|row| {
+                    let coord = IVec2::new(column, *row);
+                    Some(coord) != step
+                        && self.collision.collision_at(self.tile_map, coord) == TileCollision::Solid
+                })
+                .collect();
+
+            if !blocking.is_empty() {
+                let normal = Vec2::new(-delta.signum(), 0.0);
+                self.center.x = if delta > 0.0 {
+                    column as f32 * self.tile_size.x - self.half_size.x
+                } else {
+                    (column + 1) as f32 * self.tile_size.x + self.half_size.x
+                };
+                self.velocity.x = 0.0;
+                self.contacts.extend(
+                    blocking
+                        .into_iter()
+                        .map(|row| (IVec2::new(column, row), normal)),
+                );
+                return;
+            }
+        }
+
+        self.center.x += delta;
+    }
+
+    /// Returns true if the body landed on something.
+    fn move_y(&mut self, delta: f32) -> bool {
+        if delta == 0.0 {
+            return false;
+        }
+
+        let columns = self.tile_range(
+            self.center.x - self.half_size.x,
+            self.center.x + self.half_size.x,
+            self.tile_size.x,
+        );
+        let edge = self.center.y + self.half_size.y * delta.signum();
+        let target = edge + delta;
+
+        let rows: Vec<i32> = if delta > 0.0 {
+            let first = ((edge - SKIN) / self.tile_size.y).floor() as i32 + 1;
+            let last = ((target - SKIN) / self.tile_size.y).floor() as i32;
+            (first..=last).collect()
+        } else {
+            let first = ((edge + SKIN) / self.tile_size.y).floor() as i32 - 1;
+            let last = (target / self.tile_size.y).floor() as i32;
+            (last..=first).rev().collect()
+        };
+
+        for row in rows {
+            let blocking: Vec<i32> = columns
+                .clone()
+                .filter(|column| {
+                    match self
+                        .collision
+                        .collision_at(self.tile_map, IVec2::new(*column, row))
+                    {
+                        TileCollision::Solid => true,
+                        // One way platforms only stop bodies that started above them.
+                        TileCollision::OneWay => delta < 0.0,
+                        _ => false,
+                    }
+                })
+                .collect();
+
+            if !blocking.is_empty() {
+                let normal = Vec2::new(0.0, -delta.signum());
+                self.center.y = if delta > 0.0 {
+                    row as f32 * self.tile_size.y - self.half_size.y
+                } else {
+                    (row + 1) as f32 * self.tile_size.y + self.half_size.y
+                };
+                self.velocity.y = 0.0;
+                self.contacts.extend(
+                    blocking
+                        .into_iter()
+                        .map(|column| (IVec2::new(column, row), normal)),
+                );
+                return delta < 0.0;
+            }
+        }
+
+        self.center.y += delta;
+        false
+    }
+
+    /// Pushes the bottom center of the body on to the surface of the slope under it. Bodies that
+    /// were on the ground before moving get snapped down, so they stick to slopes going down.
+    /// Returns true if the body is standing on a slope.
+    fn resolve_slopes(&mut self, was_on_ground: bool, delta_x: f32) -> bool {
+        if self.velocity.y > 0.0 {
+            return false;
+        }
+
+        let bottom = self.center.y - self.half_size.y;
+        let column = (self.center.x / self.tile_size.x).floor() as i32;
+        let row = (bottom / self.tile_size.y).floor() as i32;
+
+        // A body walking down a slope can drop out of the tile it was on by up to `delta_x` per
+        // frame, so look one tile further down to keep it attached.
+        let snap_distance = if was_on_ground {
+            delta_x.abs() + SKIN
+        } else {
+            0.0
+        };
+
+        for row in [row, row - 1] {
+            let coord = IVec2::new(column, row);
+            let collision = self.collision.collision_at(self.tile_map, coord);
+            if !collision.is_slope() {
+                continue;
+            }
+
+            let x_in_tile = self.center.x / self.tile_size.x - column as f32;
+            let floor = (row as f32 + collision.floor_height(x_in_tile)) * self.tile_size.y;
+
+            if bottom <= floor || bottom - floor <= snap_distance {
+                self.center.y = floor + self.half_size.y;
+                self.velocity.y = 0.0;
+
+                let normal = match collision {
+                    TileCollision::SlopeUpRight => Vec2::new(-1.0, 1.0),
+                    _ => Vec2::new(1.0, 1.0),
+                };
+                self.contacts.push((coord, normal.normalize()));
+                return true;
+            }
+        }
+
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{TileKind, TileTransform};
+
+    fn tile(collision: TileCollision) -> Tile {
+        Tile {
+            entity: None,
+            kind: Some(TileKind::Sprite {
+                idx: collision as u16,
+                transform: TileTransform::default(),
+                mask_color: Color::WHITE,
+            }),
+            pickable: false,
+        }
+    }
+
+    /// A map with 1x1 tiles from `rows`, the first row being the top. `#` is solid, `=` one way,
+    /// `/` and `\` are slopes.
+    fn map(rows: &[&str]) -> (TileMap, TileMapCollision) {
+        let size = UVec2::new(rows[0].len() as u32, rows.len() as u32);
+        let mut tile_map = TileMap::new(size, size, UVec2::ONE, Handle::default());
+        for (y, row) in rows.iter().rev().enumerate() {
+            for (x, c) in row.chars().enumerate() {
+                let collision = match c {
+                    '#' => TileCollision::Solid,
+                    '=' => TileCollision::OneWay,
+                    '/' => TileCollision::SlopeUpRight,
+                    '\\' => TileCollision::SlopeUpLeft,
+                    _ => continue,
+                };
+                tile_map[(x as u32, y as u32, 0)] = tile(collision);
+            }
+        }
+
+        let collision = TileMapCollision::new(0, |tile| match tile.kind {
+            Some(TileKind::Sprite { idx, .. }) => match idx {
+                1 => TileCollision::Solid,
+                2 => TileCollision::OneWay,
+                3 => TileCollision::SlopeUpRight,
+                4 => TileCollision::SlopeUpLeft,
+                _ => TileCollision::Empty,
+            },
+            _ => TileCollision::Empty,
+        });
+        (tile_map, collision)
+    }
+
+    fn sweep<'a>(
+        tile_map: &'a TileMap,
+        collision: &'a TileMapCollision,
+        center: Vec2,
+    ) -> Sweep<'a> {
+        Sweep {
+            tile_map,
+            collision,
+            tile_size: tile_map.tile_size.as_vec2(),
+            half_size: Vec2::splat(0.25),
+            center,
+            velocity: Vec2::ZERO,
+            contacts: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn collision_outside_the_map_is_empty() {
+        let (tile_map, collision) = map(&["#..", "..#"]);
+
+        assert_eq!(
+            collision.collision_at(&tile_map, IVec2::new(2, 0)),
+            TileCollision::Solid
+        );
+        // Past the right edge of the bottom row is the start of the top row.
+        assert_eq!(
+            collision.collision_at(&tile_map, IVec2::new(3, 0)),
+            TileCollision::Empty
+        );
+        assert_eq!(
+            collision.collision_at(&tile_map, IVec2::new(0, 2)),
+            TileCollision::Empty
+        );
+        assert_eq!(
+            collision.collision_at(&tile_map, IVec2::new(-1, 1)),
+            TileCollision::Empty
+        );
+    }
+
+    #[test]
+    fn walls_stop_bodies() {
+        let (tile_map, collision) = map(&["...#", "####"]);
+        let mut sweep = sweep(&tile_map, &collision, Vec2::new(1.5, 1.25));
+        sweep.move_x(2.0);
+
+        assert_eq!(sweep.center.x, 2.75);
+        assert_eq!(
+            sweep.contacts,
+            vec![(IVec2::new(3, 1), Vec2::new(-1.0, 0.0))]
+        );
+    }
+
+    #[test]
+    fn bodies_land_on_one_way_platforms_but_jump_through_them() {
+        let (tile_map, collision) = map(&["...", ".=.", "..."]);
+
+        let mut falling = sweep(&tile_map, &collision, Vec2::new(1.5, 2.5));
+        assert!(falling.move_y(-1.0));
+        assert_eq!(falling.center.y, 2.25);
+
+        let mut jumping = sweep(&tile_map, &collision, Vec2::new(1.5, 0.5));
+        assert!(!jumping.move_y(1.0));
+        assert_eq!(jumping.center.y, 1.5);
+    }
+
+    #[test]
+    fn bodies_are_pushed_on_to_slopes() {
+        let (tile_map, collision) = map(&["....", "./..", "####"]);
+        let mut sweep = sweep(&tile_map, &collision, Vec2::new(1.75, 1.3));
+
+        assert!(sweep.resolve_slopes(false, 0.0));
+        assert!((sweep.center.y - 2.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn bodies_step_from_a_slope_on_to_the_tile_it_leads_to() {
+        let (tile_map, collision) = map(&["....", "./#.", "####"]);
+        // Standing at the top of the slope.
+        let mut sweep = sweep(&tile_map, &collision, Vec2::new(1.75, 2.0));
+        sweep.move_x(0.5);
+
+        assert_eq!(sweep.center.x, 2.25);
+        assert!(sweep.contacts.is_empty());
+    }
+
+    #[test]
+    fn one_tile_walls_next_to_a_slope_stop_bodies() {
+        let (tile_map, collision) = map(&[".....", "#./.#", "#####"]);
+
+        // Walking down the slope in to a wall at the bottom.
+        let mut down = sweep(&tile_map, &collision, Vec2::new(2.25, 1.5));
+        down.move_x(-2.0);
+        assert_eq!(down.center.x, 1.25);
+        assert_eq!(down.contacts, vec![(IVec2::new(0, 1), Vec2::new(1.0, 0.0))]);
+
+        // Walking over the top of the slope in to a wall that isn't joined to it.
+        let mut up = sweep(&tile_map, &collision, Vec2::new(2.75, 2.0));
+        up.move_x(2.0);
+        assert_eq!(up.center.x, 3.75);
+        assert_eq!(up.contacts, vec![(IVec2::new(4, 1), Vec2::new(-1.0, 0.0))]);
+    }
+}
@@ -1,75 +1,126 @@
 mod chunk;
+mod collider;
+mod diagnostics;
+mod highlight;
+#[cfg(feature = "interaction")]
 mod interaction;
+mod kinematic;
+mod provider;
+#[cfg(feature = "render")]
 mod render;
+mod selection;
+mod streaming;
 mod tile_map;
+mod tile_sheet;
 
-use bevy::{
-    core_pipeline::Transparent2d,
-    prelude::*,
-    render::{
-        render_asset::{PrepareAssetLabel, RenderAssetPlugin},
-        render_phase::AddRenderCommand,
-        render_resource::SpecializedRenderPipelines,
-        RenderApp, RenderStage,
-    },
-};
-
-use bevy_mod_raycast::RaycastSystem;
+use bevy::prelude::*;
 
-pub use interaction::{TileMapInteractionEvent, TileMapRayCastSource};
-pub use render::TileSheet;
+pub use chunk::{ChunkCoord, ChunkData, ChunkEntities};
+pub use collider::{
+    ChunkColliders, ColliderRect, ColliderSpace, TileMapColliderGenerator, TileMapColliders,
+};
+pub use diagnostics::TileMapDiagnosticsPlugin;
+pub use highlight::{TileHighlight, TileHighlightStyle, TileHighlights};
+#[cfg(feature = "interaction")]
+pub use interaction::{
+    TileCursor, TileCursorInput, TileHit, TileMapInteractionEvent, TileMapInteractionKind,
+    TileMapInteractionPlugin, TileMapModifiers, TileMapRayCastSource, TilePickMode, TileRay,
+    TileSelectMode, TileSelector,
+};
+pub use kinematic::{KinematicTileBody, TileCollision, TileContactEvent, TileMapCollision};
+pub use provider::{ChunkProvider, TileMapChunkProvider};
+#[cfg(feature = "debug")]
+pub use render::debug::{TileMapDebugPlugin, TileMapDebugSettings};
+#[cfg(feature = "render")]
+pub use render::TileMapRenderPlugin;
+pub use selection::TileSelection;
+pub use streaming::ChunkStreaming;
 pub use tile_map::*;
+pub use tile_sheet::{
+    TileAnimation, TileAnimationFrame, TileAnimationMode, TileFilter, TileSetLayout, TileSheet,
+    TileSheetError, TileSheetSampling,
+};
 
 pub mod prelude {
-    pub use super::interaction::{TileMapInteractionEvent, TileMapRayCastSource};
-    pub use super::render::TileSheet;
+    pub use super::collider::{
+        ColliderRect, ColliderSpace, TileMapColliderGenerator, TileMapColliders,
+    };
+    pub use super::diagnostics::TileMapDiagnosticsPlugin;
+    pub use super::highlight::{TileHighlight, TileHighlightStyle, TileHighlights};
+    #[cfg(feature = "interaction")]
+    pub use super::interaction::{
+        TileCursor, TileCursorInput, TileHit, TileMapInteractionEvent, TileMapInteractionKind,
+        TileMapModifiers, TileMapRayCastSource, TilePickMode, TileRay, TileSelectMode,
+        TileSelector,
+    };
+    pub use super::kinematic::{
+        KinematicTileBody, TileCollision, TileContactEvent, TileMapCollision,
+    };
+    pub use super::provider::{ChunkProvider, TileMapChunkProvider};
+    #[cfg(feature = "debug")]
+    pub use super::render::debug::{TileMapDebugPlugin, TileMapDebugSettings};
+    pub use super::selection::TileSelection;
+    pub use super::streaming::ChunkStreaming;
     pub use super::tile_map::*;
-    pub use super::TileMapPlugin;
+    pub use super::tile_sheet::{
+        TileAnimation, TileAnimationFrame, TileAnimationMode, TileFilter, TileSetLayout, TileSheet,
+        TileSheetError, TileSheetSampling,
+    };
+    pub use super::{TileMapCorePlugin, TileMapPlugin};
 }
 
-pub struct TileMapPlugin;
+/// Everything that doesn't need a window or a GPU: keeping the chunks in sync with the
+/// `TileMap`s, `AsTiles`, colliders, kinematic bodies, streaming and chunk providers. Works with
+/// `MinimalPlugins`.
+pub struct TileMapCorePlugin;
 
-impl Plugin for TileMapPlugin {
+impl Plugin for TileMapCorePlugin {
     fn build(&self, app: &mut App) {
-        app.init_resource::<render::ChunkShader>()
-            .add_plugin(RenderAssetPlugin::<TileSheet>::with_prepare_asset_label(
-                PrepareAssetLabel::PreAssetPrepare,
-            ))
-            .add_asset::<TileSheet>()
-            .add_event::<TileMapInteractionEvent>()
-            .add_plugin(interaction::TileMapRayCastPlugin::default())
+        app.add_event::<TileContactEvent>()
             .add_system_to_stage(
-                CoreStage::PreUpdate,
-                interaction::update_camera_ray.before(RaycastSystem::BuildRays),
+                CoreStage::PostUpdate,
+                tile_map::sync_as_tiles.before(chunk::generate_or_update_chunks),
             )
             .add_system_to_stage(
-                CoreStage::PreUpdate,
-                interaction::queue_interaction_events.after(RaycastSystem::UpdateRaycast),
+                CoreStage::PostUpdate,
+                streaming::stream_chunks
+                    .after(tile_map::sync_as_tiles)
+                    .before(chunk::generate_or_update_chunks),
             )
             .add_system_to_stage(
                 CoreStage::PostUpdate,
-                tile_map::sync_as_tiles.before(chunk::generate_or_update_chunks),
+                provider::request_chunks
+                    .after(streaming::stream_chunks)
+                    .before(collider::update_tile_map_colliders),
             )
-            .add_system_to_stage(CoreStage::PostUpdate, chunk::generate_or_update_chunks);
+            .add_system_to_stage(
+                CoreStage::PostUpdate,
+                collider::update_tile_map_colliders
+                    .after(tile_map::sync_as_tiles)
+                    .before(chunk::generate_or_update_chunks),
+            )
+            .add_system_to_stage(CoreStage::PostUpdate, chunk::generate_or_update_chunks)
+            .add_system_to_stage(CoreStage::Update, kinematic::move_kinematic_bodies);
+    }
+
+    fn name(&self) -> &str {
+        "Tilemap Core Plugin"
+    }
+}
+
+/// The `TileMapCorePlugin` together with the `TileMapRenderPlugin` and the
+/// `TileMapInteractionPlugin`, if their cargo features are enabled.
+pub struct TileMapPlugin;
+
+impl Plugin for TileMapPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugin(TileMapCorePlugin);
+
+        #[cfg(feature = "render")]
+        app.add_plugin(render::TileMapRenderPlugin);
 
-        let shader = app
-            .world
-            .get_resource::<render::ChunkShader>()
-            .unwrap()
-            .clone();
-        if let Ok(render_app) = app.get_sub_app_mut(RenderApp) {
-            render_app
-                .insert_resource(shader)
-                .init_resource::<render::TileMapPipeline>()
-                .init_resource::<SpecializedRenderPipelines<render::TileMapPipeline>>()
-                .init_resource::<render::TileMapMeta>()
-                .init_resource::<render::ExtractedChunks>()
-                .init_resource::<render::TileUniforms>()
-                .add_render_command::<Transparent2d, render::DrawChunk>()
-                .add_system_to_stage(RenderStage::Extract, render::extract_chunks)
-                .add_system_to_stage(RenderStage::Prepare, render::prepare_tiles)
-                .add_system_to_stage(RenderStage::Queue, render::queue_chunks);
-        };
+        #[cfg(feature = "interaction")]
+        app.add_plugin(interaction::TileMapInteractionPlugin);
     }
 
     fn name(&self) -> &str {
@@ -0,0 +1,205 @@
+use std::sync::{Arc, Mutex};
+
+use bevy::{
+    prelude::*,
+    tasks::AsyncComputeTaskPool,
+    utils::{HashMap, HashSet},
+};
+
+use crate::{chunk::ChunkCoord, streaming::ChunkStreaming, Tile, TileMap};
+
+/// Supplies the tiles of the chunks of a `TileMap`, for example by generating them or by reading
+/// them from disk. Chunk coordinates are the coordinate of the chunk in chunks with the layer as z.
+pub trait ChunkProvider: Send + Sync + 'static {
+    /// Returns the tiles of the chunk row by row, `chunk_size.x * chunk_size.y` of them, or `None`
+    /// to leave the chunk empty. Runs on the `AsyncComputeTaskPool`.
+    fn load(&self, chunk_coord: UVec3, chunk_size: UVec2) -> Option<Vec<Tile>>;
+
+    /// Called with the current tiles of a chunk before it gets evicted. Runs on the main thread.
+    fn save(&self, _chunk_coord: UVec3, _tiles: &[Tile]) {}
+}
+
+/// Add this next to a `TileMap` to load its chunks from a `ChunkProvider` when they are first
+/// needed. Without `ChunkStreaming` every chunk of the map is needed right away, with it chunks
+/// are loaded when they come in to range and saved and cleared from the `TileMap` when they
+/// are unloaded.
+#[derive(Component)]
+pub struct TileMapChunkProvider {
+    provider: Arc<dyn ChunkProvider>,
+    // The ticket of the running load of each chunk. A chunk that gets evicted and requested again
+    // while it is loading gets a new ticket, so the result of the first load is dropped.
+    pending: HashMap<ChunkCoord, u64>,
+    loaded: HashSet<ChunkCoord>,
+    next_ticket: u64,
+    results: LoadResults,
+}
+
+// Filled by the load tasks.
+type LoadResults = Arc<Mutex<Vec<(ChunkCoord, u64, Option<Vec<Tile>>)>>>;
+
+impl TileMapChunkProvider {
+    pub fn new(provider: impl ChunkProvider) -> Self {
+        TileMapChunkProvider {
+            provider: Arc::new(provider),
+            pending: HashMap::default(),
+            loaded: HashSet::default(),
+            next_ticket: 0,
+            results: Arc::default(),
+        }
+    }
+
+    /// True if the chunk was requested from the provider but didn't arrive yet.
+    #[inline]
+    pub fn is_pending(&self, chunk_coord: UVec3) -> bool {
+        self.pending.contains_key(&ChunkCoord(chunk_coord))
+    }
+
+    #[inline]
+    fn is_requested(&self, chunk_coord: ChunkCoord) -> bool {
+        self.loaded.contains(&chunk_coord) || self.pending.contains_key(&chunk_coord)
+    }
+
+    fn request(&mut self, chunk_coord: ChunkCoord, chunk_size: UVec2, pool: &AsyncComputeTaskPool) {
+        if self.is_requested(chunk_coord) {
+            return;
+        }
+
+        let ticket = self.next_ticket;
+        self.next_ticket += 1;
+        self.pending.insert(chunk_coord, ticket);
+
+        let provider = self.provider.clone();
+        let results = self.results.clone();
+        pool.spawn(async move {
+            let tiles = provider.load(chunk_coord.0, chunk_size);
+            results.lock().unwrap().push((chunk_coord, ticket, tiles));
+        })
+        .detach();
+    }
+
+    fn has_results(&self) -> bool {
+        !self.results.lock().unwrap().is_empty()
+    }
+
+    /// Writes the chunks that finished loading in to `tile_map`.
+    fn apply_loaded(&mut self, tile_map: &mut TileMap) {
+        let results = std::mem::take(&mut *self.results.lock().unwrap());
+        for (chunk_coord, ticket, tiles) in results {
+            // The chunk got evicted while it was loading, and maybe requested again since.
+            if self.pending.get(&chunk_coord) != Some(&ticket) {
+                continue;
+            }
+
+            self.pending.remove(&chunk_coord);
+            self.loaded.insert(chunk_coord);
+            if let Some(tiles) = tiles {
+                tile_map.set_chunk_tiles(chunk_coord, &tiles);
+            }
+        }
+    }
+}
+
+/// Applies the chunks that finished loading, evicts the chunks that got unloaded and requests the
+/// chunks that are needed. Runs before the colliders are updated, so they see the loaded and
+/// evicted tiles in the same frame.
+pub(crate) fn request_chunks(
+    pool: Res<AsyncComputeTaskPool>,
+    mut tile_maps: Query<(
+        &mut TileMap,
+        &mut TileMapChunkProvider,
+        Option<&ChunkStreaming>,
+    )>,
+) {
+    for (mut tile_map, mut provider, streaming) in tile_maps.iter_mut() {
+        // Only take the tile map and the provider mutably when something changes, so their change
+        // detection doesn't fire every frame.
+        if provider.has_results() {
+            provider.apply_loaded(&mut tile_map);
+        }
+
+        let chunk_size = tile_map.chunk_size;
+        let chunk_count =
+            (tile_map.size.truncate() + tile_map.chunk_size - UVec2::ONE) / tile_map.chunk_size;
+
+        if let Some(streaming) = streaming {
+            let evicted: Vec<ChunkCoord> = provider
+                .loaded
+                .iter()
+                .chain(provider.pending.keys())
+                .copied()
+                .filter(|chunk_coord| !streaming.is_loaded(*chunk_coord))
+                .collect();
+            for chunk_coord in evicted {
+                provider.pending.remove(&chunk_coord);
+                if provider.loaded.remove(&chunk_coord) {
+                    provider
+                        .provider
+                        .save(chunk_coord.0, &tile_map.chunk_tiles(chunk_coord));
+                    tile_map.set_chunk_tiles(
+                        chunk_coord,
+                        &vec![Tile::default(); (chunk_size.x * chunk_size.y) as usize],
+                    );
+                }
+            }
+
+            for column in streaming.loaded.iter() {
+                for z in 0..tile_map.size.z {
+                    let chunk_coord = ChunkCoord(column.extend(z));
+                    if !provider.is_requested(chunk_coord) {
+                        provider.request(chunk_coord, chunk_size, &pool);
+                    }
+                }
+            }
+        } else if provider.loaded.len() + provider.pending.len()
+            < (chunk_count.x * chunk_count.y * tile_map.size.z) as usize
+        {
+            for x in 0..chunk_count.x {
+                for y in 0..chunk_count.y {
+                    for z in 0..tile_map.size.z {
+                        provider.request(ChunkCoord(UVec3::new(x, y, z)), chunk_size, &pool);
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Empty;
+
+    impl ChunkProvider for Empty {
+        fn load(&self, _chunk_coord: UVec3, _chunk_size: UVec2) -> Option<Vec<Tile>> {
+            None
+        }
+    }
+
+    #[test]
+    fn results_of_an_evicted_load_are_dropped() {
+        let mut tile_map = TileMap::new(UVec2::ONE, UVec2::ONE, UVec2::ONE, Handle::default());
+        let mut provider = TileMapChunkProvider::new(Empty);
+        let chunk_coord = ChunkCoord(UVec3::ZERO);
+
+        // The first load got evicted and the chunk was requested again.
+        provider.pending.insert(chunk_coord, 1);
+        provider
+            .results
+            .lock()
+            .unwrap()
+            .push((chunk_coord, 0, None));
+        provider.apply_loaded(&mut tile_map);
+        assert!(provider.is_pending(chunk_coord.0));
+        assert!(!provider.loaded.contains(&chunk_coord));
+
+        provider
+            .results
+            .lock()
+            .unwrap()
+            .push((chunk_coord, 1, None));
+        provider.apply_loaded(&mut tile_map);
+        assert!(!provider.is_pending(chunk_coord.0));
+        assert!(provider.loaded.contains(&chunk_coord));
+    }
+}
@@ -5,6 +5,17 @@ struct View {
 [[group(0), binding(0)]]
 var<uniform> view: View;
 
+// Keep in sync with `GlobalsUniform`.
+struct Globals {
+    time: f32;
+    dirty_flash_duration: f32;
+    grid_color: vec4<f32>;
+    chunk_border_color: vec4<f32>;
+    dirty_flash_color: vec4<f32>;
+};
+[[group(0), binding(1)]]
+var<uniform> globals: Globals;
+
 struct Transform {
     [[location(0)]] row_0: vec4<f32>;
     [[location(1)]] row_1: vec4<f32>;
@@ -15,12 +26,22 @@ struct Transform {
 struct Chunk {
     [[location(4)]] chunk_size: vec2<u32>;
     [[location(5)]] tile_size: vec2<u32>;
+    // Where the tiles of the chunk start in `tiles`.
+    [[location(6)]] tile_offset: u32;
+    // Seconds since the tiles of the chunk last changed.
+    [[location(7)]] since_changed: f32;
+    // The first highlight of the chunk in `highlights` and how many it has.
+    [[location(8)]] highlights: vec2<u32>;
 };
 
 struct VertexOutput {
     [[builtin(position)]] position: vec4<f32>;
     [[location(0)]] uv: vec2<f32>;
     [[location(1)]] tile_index: u32;
+    // The position on the whole chunk, from 0 to 1.
+    [[location(2)]] chunk_uv: vec2<f32>;
+    [[location(3)]] since_changed: f32;
+    [[location(4)]] highlights: vec2<u32>;
 };
 
 [[stage(vertex)]]
@@ -43,15 +64,18 @@ fn vertex([[builtin(vertex_index)]] index: u32, transform: Transform, chunk: Chu
     var out: VertexOutput;
     out.position = view.view_proj * world_position;
     out.uv = vec2<f32>(corner_position);
-    out.tile_index = tile_index;
+    out.tile_index = chunk.tile_offset + tile_index;
+    out.chunk_uv = vec2<f32>(tile_position + corner_position) / vec2<f32>(chunk.chunk_size);
+    out.since_changed = chunk.since_changed;
+    out.highlights = chunk.highlights;
 
     return out;
 }
 
+// Keep in sync with `encoding.rs`.
 struct Tile {
-    idx: i32;
-    transform: mat4x4<f32>;
-    mask_color: vec4<f32>;
+    packed: u32;
+    color: u32;
 };
 
 struct Tiles {
@@ -60,23 +84,230 @@ struct Tiles {
 [[group(1), binding(0)]]
 var<storage, read> tiles: Tiles;
 
+struct TileTransform {
+    matrix: vec4<f32>;
+    translation: vec4<f32>;
+};
+
+struct TileTransforms {
+    data: array<TileTransform>;
+};
+[[group(1), binding(1)]]
+var<storage, read> transforms: TileTransforms;
+
+// Keep in sync with `HighlightUniform`.
+struct Highlight {
+    // The index of the tile in `tiles`.
+    tile: u32;
+    color: u32;
+    style: u32;
+};
+
+struct Highlights {
+    data: array<Highlight>;
+};
+[[group(1), binding(2)]]
+var<storage, read> highlights: Highlights;
+
+let KIND_NONE: u32 = 0u;
+let KIND_COLOR: u32 = 1u;
+let KIND_ANIMATED_SPRITE: u32 = 3u;
+
 [[group(2), binding(0)]]
 var texture_array: texture_2d_array<f32>;
 [[group(2), binding(1)]]
 var texture_sampler: sampler;
 
+struct Animation {
+    first_frame: u32;
+    frame_count: u32;
+    mode: u32;
+    duration: f32;
+};
+
+struct Animations {
+    data: array<Animation>;
+};
+[[group(2), binding(2)]]
+var<storage, read> animations: Animations;
+
+struct AnimationFrame {
+    idx: u32;
+    end: f32;
+};
+
+struct AnimationFrames {
+    data: array<AnimationFrame>;
+};
+[[group(2), binding(3)]]
+var<storage, read> animation_frames: AnimationFrames;
+
+let ANIMATION_MODE_LOOP: u32 = 0u;
+let ANIMATION_MODE_PING_PONG: u32 = 1u;
+
+// Keep in sync with `TileAnimation::frame_at`.
+fn animation_frame(animation_idx: i32) -> i32 {
+    let animation = animations.data[animation_idx];
+    // Animations without any length show their first frame, like `TileAnimation::frame_at`.
+    if (animation.duration <= 0.0) {
+        if (animation.frame_count == 0u) {
+            return 0;
+        }
+        return i32(animation_frames.data[animation.first_frame].idx);
+    }
+
+    var time = globals.time;
+    if (animation.mode == ANIMATION_MODE_LOOP) {
+        time = time % animation.duration;
+    } else if (animation.mode == ANIMATION_MODE_PING_PONG) {
+        time = time % (animation.duration * 2.0);
+        if (time > animation.duration) {
+            time = animation.duration * 2.0 - time;
+        }
+    } else {
+        time = min(time, animation.duration);
+    }
+
+    var idx = 0u;
+    for (var i = 0u; i < animation.frame_count; i = i + 1u) {
+        let frame = animation_frames.data[animation.first_frame + i];
+        idx = frame.idx;
+        if (time < frame.end) {
+            break;
+        }
+    }
+
+    return i32(idx);
+}
+
+let FLIP_X: u32 = 1u;
+let FLIP_Y: u32 = 2u;
+let FLIP_ANTI_DIAGONAL: u32 = 4u;
+
+// Keep in sync with `TileFlip::apply`.
+fn apply_flip(uv: vec2<f32>, flip: u32) -> vec2<f32> {
+    var uv = uv;
+    if ((flip & FLIP_Y) != 0u) {
+        uv.y = 1.0 - uv.y;
+    }
+    if ((flip & FLIP_X) != 0u) {
+        uv.x = 1.0 - uv.x;
+    }
+    if ((flip & FLIP_ANTI_DIAGONAL) != 0u) {
+        uv = vec2<f32>(1.0 - uv.y, 1.0 - uv.x);
+    }
+    return uv;
+}
+
+let HIGHLIGHT_TINT: u32 = 1u;
+let HIGHLIGHT_OUTLINE: u32 = 2u;
+let HIGHLIGHT_PULSE: u32 = 3u;
+
+// Blends `top` over `bottom`.
+fn blend_over(top: vec4<f32>, bottom: vec4<f32>) -> vec4<f32> {
+    let alpha = top.a + bottom.a * (1.0 - top.a);
+    if (alpha <= 0.0) {
+        return vec4<f32>(0.0);
+    }
+    return vec4<f32>((top.rgb * top.a + bottom.rgb * bottom.a * (1.0 - top.a)) / alpha, alpha);
+}
+
+// Whether `uv` is within `width` screen pixels of an edge, `uv_width` is how much `uv` changes per
+// screen pixel.
+fn on_edge(uv: vec2<f32>, uv_width: vec2<f32>, width: f32) -> bool {
+    let edge = min(uv, vec2<f32>(1.0) - uv);
+    return edge.x < width * uv_width.x || edge.y < width * uv_width.y;
+}
+
+// The highlight of tile `tile_index` among the `range.y` highlights starting at `range.x`, which
+// are sorted by tile. Its style is 0 if the tile isn't highlighted.
+fn find_highlight(tile_index: u32, range: vec2<u32>) -> Highlight {
+    var low = range.x;
+    var high = range.x + range.y;
+    loop {
+        if (low >= high) {
+            break;
+        }
+        let middle = (low + high) / 2u;
+        let highlight = highlights.data[middle];
+        if (highlight.tile == tile_index) {
+            return highlight;
+        } else if (highlight.tile < tile_index) {
+            low = middle + 1u;
+        } else {
+            high = middle;
+        }
+    }
+
+    var none: Highlight;
+    none.style = 0u;
+    return none;
+}
+
+// `uv` is the position on the tile and `uv_width` how much it changes per screen pixel.
+fn apply_highlight(color: vec4<f32>, highlight: Highlight, uv: vec2<f32>, uv_width: vec2<f32>) -> vec4<f32> {
+    var highlight_color = unpack4x8unorm(highlight.color);
+    if (highlight.style == HIGHLIGHT_OUTLINE) {
+        if (!on_edge(uv, uv_width, 2.0)) {
+            return color;
+        }
+    } else if (highlight.style == HIGHLIGHT_PULSE) {
+        highlight_color.a = highlight_color.a * (0.5 + 0.5 * sin(globals.time * 6.0));
+    } else if (highlight.style != HIGHLIGHT_TINT) {
+        return color;
+    }
+    return blend_over(highlight_color, color);
+}
+
+// The overlays of the `TileMapDebugPlugin`, the colors are transparent without it.
+fn apply_debug(color: vec4<f32>, in: VertexOutput, uv_width: vec2<f32>, chunk_uv_width: vec2<f32>) -> vec4<f32> {
+    var out = color;
+    if (globals.dirty_flash_duration > 0.0) {
+        var flash = globals.dirty_flash_color;
+        flash.a = flash.a * clamp(1.0 - in.since_changed / globals.dirty_flash_duration, 0.0, 1.0);
+        out = blend_over(flash, out);
+    }
+    if (on_edge(in.uv, uv_width, 1.0)) {
+        out = blend_over(globals.grid_color, out);
+    }
+    if (on_edge(in.chunk_uv, chunk_uv_width, 2.0)) {
+        out = blend_over(globals.chunk_border_color, out);
+    }
+    return out;
+}
+
 [[stage(fragment)]]
 fn fragment(in: VertexOutput) -> [[location(0)]] vec4<f32> {
     let tile = tiles.data[in.tile_index];
 
-    let uv = tile.transform * vec4<f32>(in.uv, 1.0, 1.0);
-    let color = textureSample(texture_array, texture_sampler, uv.xy, tile.idx);
+    var idx = i32(tile.packed & 0xffffu);
+    let kind = (tile.packed >> 16u) & 3u;
+    let flip = (tile.packed >> 18u) & 7u;
+    let transform = transforms.data[tile.packed >> 21u];
+    let mask_color = unpack4x8unorm(tile.color);
 
-    if (tile.idx == -2 || uv.x > 1.01 || uv.x < -0.01 || uv.y > 1.01 || uv.y < -0.01) {
-        discard;
-    } else if (tile.idx == -1) {
-        return tile.mask_color;
+    if (kind == KIND_ANIMATED_SPRITE) {
+        idx = animation_frame(idx);
     }
 
-    return color * tile.mask_color;
-}
\ No newline at end of file
+    let matrix = mat2x2<f32>(transform.matrix.xy, transform.matrix.zw);
+    let uv = apply_flip(matrix * in.uv + transform.translation.xy, flip);
+    // The rows of the sprites are stored from the top.
+    let color = textureSample(texture_array, texture_sampler, vec2<f32>(uv.x, 1.0 - uv.y), idx);
+    let uv_width = fwidth(in.uv);
+    let chunk_uv_width = fwidth(in.chunk_uv);
+
+    var out = color * mask_color;
+    if (kind == KIND_NONE || uv.x > 1.01 || uv.x < -0.01 || uv.y > 1.01 || uv.y < -0.01) {
+        out = vec4<f32>(0.0);
+    } else if (kind == KIND_COLOR) {
+        out = mask_color;
+    }
+
+    out = apply_highlight(out, find_highlight(in.tile_index, in.highlights), in.uv, uv_width);
+    out = apply_debug(out, in, uv_width, chunk_uv_width);
+    if (out.a <= 0.0) {
+        discard;
+    }
+    return out;
+}
@@ -0,0 +1,203 @@
+use bevy::{
+    prelude::*,
+    render::{RenderApp, RenderStage, RenderWorld},
+    utils::{HashMap, HashSet},
+};
+
+use super::TileMapGlobals;
+use crate::TileMap;
+
+/// Draws debug overlays over every `TileMap`, configured with the `TileMapDebugSettings`
+/// resource. Needs the `TileMapRenderPlugin` and the `debug` feature.
+pub struct TileMapDebugPlugin;
+
+impl Plugin for TileMapDebugPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<TileMapDebugSettings>()
+            .add_system_to_stage(CoreStage::PostUpdate, update_coordinate_labels);
+
+        if let Ok(render_app) = app.get_sub_app_mut(RenderApp) {
+            render_app.add_system_to_stage(RenderStage::Extract, extract_debug_settings);
+        }
+    }
+
+    fn name(&self) -> &str {
+        "Tilemap Debug Plugin"
+    }
+}
+
+/// What the `TileMapDebugPlugin` draws, `None` turns an overlay off.
+#[derive(Debug, Clone)]
+pub struct TileMapDebugSettings {
+    /// Lines along the edges of every tile of the spawned chunks.
+    pub grid: Option<Color>,
+    /// Lines along the edges of every spawned chunk.
+    pub chunk_borders: Option<Color>,
+    /// Blended over a chunk when its tiles get rebuilt, fading out over `dirty_flash_duration`
+    /// seconds.
+    pub dirty_flash: Option<Color>,
+    pub dirty_flash_duration: f32,
+    /// Labels the tiles in view of a camera with their x and y coordinate in this font.
+    pub coordinate_font: Option<Handle<Font>>,
+    pub coordinate_color: Color,
+    /// Tile maps with more tiles than this in view of a camera don't get labels.
+    pub max_coordinate_labels: u32,
+}
+
+impl Default for TileMapDebugSettings {
+    fn default() -> Self {
+        TileMapDebugSettings {
+            grid: Some(Color::rgba(1.0, 1.0, 1.0, 0.2)),
+            chunk_borders: Some(Color::rgba(1.0, 1.0, 0.0, 0.8)),
+            dirty_flash: Some(Color::rgba(1.0, 0.0, 0.0, 0.4)),
+            dirty_flash_duration: 0.5,
+            coordinate_font: None,
+            coordinate_color: Color::WHITE,
+            max_coordinate_labels: 1024,
+        }
+    }
+}
+
+pub(crate) fn extract_debug_settings(
+    settings: Res<TileMapDebugSettings>,
+    mut render_world: ResMut<RenderWorld>,
+) {
+    let to_vec4 = |color: Option<Color>| Vec4::from(color.unwrap_or(Color::NONE).as_rgba_f32());
+
+    let mut globals = render_world.resource_mut::<TileMapGlobals>();
+    globals.globals.grid_color = to_vec4(settings.grid);
+    globals.globals.chunk_border_color = to_vec4(settings.chunk_borders);
+    globals.globals.dirty_flash_color = to_vec4(settings.dirty_flash);
+    globals.globals.dirty_flash_duration = if settings.dirty_flash.is_some() {
+        settings.dirty_flash_duration
+    } else {
+        0.0
+    };
+}
+
+pub(crate) fn update_coordinate_labels(
+    mut commands: Commands,
+    settings: Res<TileMapDebugSettings>,
+    cameras: Query<(&Camera, &GlobalTransform)>,
+    tile_maps: Query<(Entity, &TileMap, &GlobalTransform)>,
+    // The labels by tile map and coordinate, they are children of their tile map.
+    mut labels: Local<HashMap<(Entity, UVec2), Entity>>,
+) {
+    let mut wanted = HashSet::new();
+    if let Some(font) = &settings.coordinate_font {
+        for (camera, camera_transform) in cameras.iter() {
+            for (entity, tile_map, transform) in tile_maps.iter() {
+                let (min, max) = if let Some(range) =
+                    visible_tiles(camera, camera_transform, tile_map, transform)
+                {
+                    range
+                } else {
+                    continue;
+                };
+
+                let count = max - min;
+                if count.x * count.y > settings.max_coordinate_labels {
+                    continue;
+                }
+
+                for y in min.y..max.y {
+                    for x in min.x..max.x {
+                        wanted.insert((entity, UVec2::new(x, y)));
+                    }
+                }
+            }
+        }
+
+        // New settings respawn every label.
+        if settings.is_changed() {
+            for (_, label) in labels.drain() {
+                commands.entity(label).despawn();
+            }
+        }
+
+        for (entity, coord) in wanted.iter().copied() {
+            if labels.contains_key(&(entity, coord)) {
+                continue;
+            }
+            let tile_map = tile_maps.get_component::<TileMap>(entity).unwrap();
+
+            let tile_size = tile_map.tile_size.as_vec2();
+            let position = (coord.as_vec2() + Vec2::splat(0.5)) * tile_size;
+            let label = commands
+                .spawn_bundle(Text2dBundle {
+                    text: Text::with_section(
+                        format!("{},{}", coord.x, coord.y),
+                        TextStyle {
+                            font: font.clone(),
+                            font_size: tile_size.y / 3.0,
+                            color: settings.coordinate_color,
+                        },
+                        TextAlignment {
+                            vertical: VerticalAlign::Center,
+                            horizontal: HorizontalAlign::Center,
+                        },
+                    ),
+                    // Chunks are one unit apart per layer, so this is above all of them.
+                    transform: Transform::from_translation(position.extend(tile_map.size.z as f32)),
+                    ..Default::default()
+                })
+                .id();
+            commands.entity(entity).add_child(label);
+            labels.insert((entity, coord), label);
+        }
+    }
+
+    labels.retain(|(entity, coord), label| {
+        if wanted.contains(&(*entity, *coord)) {
+            return true;
+        }
+        // Labels of despawned tile maps were despawned with them.
+        if tile_maps.get(*entity).is_ok() {
+            commands.entity(*label).despawn();
+        }
+        false
+    });
+}
+
+/// The range of tiles of `tile_map` in view of the camera, `None` if the camera doesn't look at
+/// its plane.
+fn visible_tiles(
+    camera: &Camera,
+    camera_transform: &GlobalTransform,
+    tile_map: &TileMap,
+    transform: &GlobalTransform,
+) -> Option<(UVec2, UVec2)> {
+    let ndc_to_local = transform.compute_matrix().inverse()
+        * camera_transform.compute_matrix()
+        * camera.projection_matrix.inverse();
+
+    let mut min = Vec2::splat(f32::MAX);
+    let mut max = Vec2::splat(f32::MIN);
+    for corner in [
+        Vec2::new(-1.0, -1.0),
+        Vec2::new(1.0, -1.0),
+        Vec2::new(-1.0, 1.0),
+        Vec2::new(1.0, 1.0),
+    ] {
+        // The near plane is at 1 with the reversed depth of bevy.
+        let near = ndc_to_local.project_point3(corner.extend(1.0));
+        let direction = ndc_to_local.project_point3(corner.extend(0.5)) - near;
+
+        let distance = -near.z / direction.z;
+        if !distance.is_finite() || distance < 0.0 {
+            return None;
+        }
+        let point = (near + direction * distance).truncate();
+        min = min.min(point);
+        max = max.max(point);
+    }
+
+    let tile_size = tile_map.tile_size.as_vec2();
+    let size = tile_map.size.truncate().as_vec2();
+    let min = (min / tile_size).floor().clamp(Vec2::ZERO, size);
+    let max = (max / tile_size).ceil().clamp(Vec2::ZERO, size);
+    if min.cmpge(max).any() {
+        return None;
+    }
+    Some((min.as_uvec2(), max.as_uvec2()))
+}
@@ -0,0 +1,362 @@
+use bevy::{
+    math::const_vec4,
+    prelude::*,
+    utils::{tracing::warn, HashMap},
+};
+use bytemuck::{Pod, Zeroable};
+
+use crate::{Tile, TileHighlight, TileHighlightStyle, TileKind, TileTransform};
+
+const KIND_SHIFT: u32 = 16;
+const FLIP_SHIFT: u32 = 18;
+const TRANSFORM_SHIFT: u32 = 21;
+
+const KIND_MASK: u32 = 0b11 << KIND_SHIFT;
+const KIND_COLOR: u32 = 1;
+const KIND_SPRITE: u32 = 2;
+const KIND_ANIMATED_SPRITE: u32 = 3;
+
+const HIGHLIGHT_TINT: u32 = 1;
+const HIGHLIGHT_OUTLINE: u32 = 2;
+const HIGHLIGHT_PULSE: u32 = 3;
+
+/// The most transforms a `TransformTable` can hold. Index 0 is always the identity.
+const MAX_TRANSFORMS: usize = 1 << (32 - TRANSFORM_SHIFT);
+
+/// 8 bytes per tile. Tiles without a transform don't need anything else.
+#[repr(C)]
+#[derive(Clone, Copy, Default, Pod, Zeroable)]
+pub struct TileUniform {
+    // Bits 0..16 are the sprite or animation index, 16..18 the kind (0 is an empty tile), 18..21
+    // the `TileFlip` and 21..32 the index in to the `TransformTable`.
+    packed: u32,
+    // RGBA8, either the color of a color tile or the mask color of a sprite.
+    color: u32,
+}
+
+/// A highlighted tile. Only few tiles are highlighted at a time, so they are kept in a separate
+/// buffer instead of growing every tile.
+#[repr(C)]
+#[derive(Clone, Copy, Default, PartialEq, Pod, Zeroable)]
+pub struct HighlightUniform {
+    // The index of the tile in the tile buffer of its pool.
+    tile: u32,
+    // RGBA8.
+    color: u32,
+    style: u32,
+}
+
+impl HighlightUniform {
+    pub fn new(tile: u32, highlight: &TileHighlight) -> Self {
+        HighlightUniform {
+            tile,
+            color: pack_color(&highlight.color),
+            style: match highlight.style {
+                TileHighlightStyle::Tint => HIGHLIGHT_TINT,
+                TileHighlightStyle::Outline => HIGHLIGHT_OUTLINE,
+                TileHighlightStyle::Pulse => HIGHLIGHT_PULSE,
+            },
+        }
+    }
+}
+
+/// A 2x3 affine transform, padded to two vec4's.
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+pub struct TileTransformUniform {
+    // The columns of the 2x2 matrix.
+    matrix: Vec4,
+    translation: Vec4,
+}
+
+impl TileTransformUniform {
+    const IDENTITY: TileTransformUniform = TileTransformUniform {
+        matrix: const_vec4!([1.0, 0.0, 0.0, 1.0]),
+        translation: Vec4::ZERO,
+    };
+
+    fn bits(&self) -> [u32; 6] {
+        // Adding 0.0 turns -0.0 in to 0.0, so untransformed tiles match the identity.
+        [
+            self.matrix.x,
+            self.matrix.y,
+            self.matrix.z,
+            self.matrix.w,
+            self.translation.x,
+            self.translation.y,
+        ]
+        .map(|value| (value + 0.0).to_bits())
+    }
+}
+
+impl From<&TileTransform> for TileTransformUniform {
+    fn from(transform: &TileTransform) -> Self {
+        let matrix: Mat3 = transform.into();
+
+        TileTransformUniform {
+            matrix: Vec4::new(
+                matrix.x_axis.x,
+                matrix.x_axis.y,
+                matrix.y_axis.x,
+                matrix.y_axis.y,
+            ),
+            translation: matrix.z_axis.truncate().extend(0.0).extend(0.0),
+        }
+    }
+}
+
+fn pack_color(color: &Color) -> u32 {
+    let [r, g, b, a] = color.as_rgba_f32();
+    let channel = |value: f32| (value.clamp(0.0, 1.0) * 255.0).round() as u32;
+
+    channel(r) | channel(g) << 8 | channel(b) << 16 | channel(a) << 24
+}
+
+/// The deduplicated transforms of every chunk in a `TilePool`. Entries are reference counted by
+/// the tiles using them, so the table only holds the transforms that are still on screen.
+pub struct TransformTable {
+    transforms: Vec<TileTransformUniform>,
+    ref_counts: Vec<u32>,
+    indices: HashMap<[u32; 6], u32>,
+    free: Vec<u32>,
+    changed: bool,
+}
+
+impl Default for TransformTable {
+    fn default() -> Self {
+        let mut indices = HashMap::default();
+        indices.insert(TileTransformUniform::IDENTITY.bits(), 0);
+
+        TransformTable {
+            transforms: vec![TileTransformUniform::IDENTITY],
+            ref_counts: vec![0],
+            indices,
+            free: Vec::new(),
+            changed: true,
+        }
+    }
+}
+
+impl TransformTable {
+    #[inline]
+    pub fn transforms(&self) -> &[TileTransformUniform] {
+        &self.transforms
+    }
+
+    /// Returns true once after the table changed.
+    #[inline]
+    pub fn take_changed(&mut self) -> bool {
+        std::mem::take(&mut self.changed)
+    }
+
+    /// Returns `None` if the table is full.
+    fn acquire(&mut self, transform: TileTransformUniform) -> Option<u32> {
+        let bits = transform.bits();
+        if let Some(index) = self.indices.get(&bits) {
+            self.ref_counts[*index as usize] += 1;
+            return Some(*index);
+        }
+
+        let index = if let Some(index) = self.free.pop() {
+            self.transforms[index as usize] = transform;
+            self.ref_counts[index as usize] = 1;
+            index
+        } else if self.transforms.len() < MAX_TRANSFORMS {
+            self.transforms.push(transform);
+            self.ref_counts.push(1);
+            self.transforms.len() as u32 - 1
+        } else {
+            return None;
+        };
+
+        self.indices.insert(bits, index);
+        self.changed = true;
+        Some(index)
+    }
+
+    fn release(&mut self, index: u32) {
+        // The identity is never freed.
+        if index == 0 {
+            return;
+        }
+
+        let ref_count = &mut self.ref_counts[index as usize];
+        *ref_count -= 1;
+        if *ref_count == 0 {
+            self.indices.remove(&self.transforms[index as usize].bits());
+            self.free.push(index);
+        }
+    }
+
+    /// Releases the transforms used by `tiles`.
+    pub fn release_tiles(&mut self, tiles: &[TileUniform]) {
+        for tile in tiles {
+            if tile.packed & KIND_MASK != 0 {
+                self.release(tile.packed >> TRANSFORM_SHIFT);
+            }
+        }
+    }
+
+    /// Encodes `tiles` in to `out`, which has to be just as long.
+    pub fn encode(&mut self, tiles: &[Tile], out: &mut [TileUniform]) {
+        let mut overflowed = false;
+        for (tile, out) in tiles.iter().zip(out.iter_mut()) {
+            let (kind, idx, transform, color) = match &tile.kind {
+                Some(TileKind::Color { color, transform }) => (KIND_COLOR, 0, transform, color),
+                Some(TileKind::Sprite {
+                    idx,
+                    transform,
+                    mask_color,
+                }) => (KIND_SPRITE, *idx, transform, mask_color),
+                Some(TileKind::AnimatedSprite {
+                    animation,
+                    transform,
+                    mask_color,
+                }) => (KIND_ANIMATED_SPRITE, *animation, transform, mask_color),
+                None => {
+                    *out = TileUniform::default();
+                    continue;
+                }
+            };
+
+            let transform_index = self
+                .acquire(TileTransformUniform::from(transform))
+                .unwrap_or_else(|| {
+                    overflowed = true;
+                    0
+                });
+
+            *out = TileUniform {
+                packed: idx as u32
+                    | kind << KIND_SHIFT
+                    | (transform.flip.bits() as u32) << FLIP_SHIFT
+                    | transform_index << TRANSFORM_SHIFT,
+                color: pack_color(color),
+            };
+        }
+
+        if overflowed {
+            warn!(
+                "Chunks sharing a tile sheet and chunk size use more than {} different tile transforms, the rest are drawn untransformed",
+                MAX_TRANSFORMS - 1
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TileFlip;
+
+    fn sprite(transform: TileTransform) -> Tile {
+        Tile {
+            entity: None,
+            kind: Some(TileKind::Sprite {
+                idx: 7,
+                transform,
+                mask_color: Color::WHITE,
+            }),
+            pickable: false,
+        }
+    }
+
+    fn rotated(angle: f32) -> TileTransform {
+        TileTransform {
+            angle,
+            ..Default::default()
+        }
+    }
+
+    fn transform_index(tile: &TileUniform) -> u32 {
+        tile.packed >> TRANSFORM_SHIFT
+    }
+
+    #[test]
+    fn pack_color_is_rgba8() {
+        assert_eq!(
+            pack_color(&Color::rgba(1.0, 0.0, 0.5, 0.25)),
+            255 | 128 << 16 | 64 << 24
+        );
+        assert_eq!(
+            pack_color(&Color::rgba(2.0, -1.0, 0.0, 1.0)),
+            255 | 255 << 24
+        );
+    }
+
+    #[test]
+    fn tiles_are_packed() {
+        let mut table = TransformTable::default();
+        let mut tile = sprite(TileTransform::default());
+        if let Some(TileKind::Sprite { transform, .. }) = &mut tile.kind {
+            transform.flip = TileFlip::Y;
+        }
+        let mut out = [TileUniform::default(); 2];
+        table.encode(&[tile, Tile::default()], &mut out);
+
+        assert_eq!(
+            out[0].packed,
+            7 | KIND_SPRITE << KIND_SHIFT | (TileFlip::Y.bits() as u32) << FLIP_SHIFT
+        );
+        assert_eq!(out[0].color, u32::MAX);
+        assert_eq!(out[1].packed, 0);
+    }
+
+    #[test]
+    fn identical_transforms_share_an_entry() {
+        let mut table = TransformTable::default();
+        let mut out = [TileUniform::default(); 3];
+        table.encode(
+            &[
+                sprite(TileTransform::default()),
+                sprite(rotated(1.0)),
+                sprite(rotated(1.0)),
+            ],
+            &mut out,
+        );
+
+        assert_eq!(transform_index(&out[0]), 0);
+        assert_eq!(transform_index(&out[1]), 1);
+        assert_eq!(transform_index(&out[2]), 1);
+        assert_eq!(table.transforms().len(), 2);
+        assert_eq!(table.ref_counts[1], 2);
+    }
+
+    #[test]
+    fn transforms_are_freed_once_unused() {
+        let mut table = TransformTable::default();
+        let mut first = [TileUniform::default(); 2];
+        table.encode(&[sprite(rotated(1.0)), sprite(rotated(1.0))], &mut first);
+        assert!(table.take_changed());
+        assert!(!table.take_changed());
+
+        table.release_tiles(&first[..1]);
+        assert_eq!(table.ref_counts[1], 1);
+        assert!(table.free.is_empty());
+
+        table.release_tiles(&first[1..]);
+        assert_eq!(table.free, vec![1]);
+
+        // The freed entry is reused instead of growing the table.
+        let mut second = [TileUniform::default(); 1];
+        table.encode(&[sprite(rotated(2.0))], &mut second);
+        assert_eq!(transform_index(&second[0]), 1);
+        assert_eq!(table.transforms().len(), 2);
+        assert!(table.take_changed());
+    }
+
+    #[test]
+    fn the_identity_is_never_freed() {
+        let mut table = TransformTable::default();
+        let mut out = [TileUniform::default(); 1];
+        table.encode(&[sprite(TileTransform::default())], &mut out);
+        table.release_tiles(&out);
+        table.release_tiles(&out);
+
+        assert!(table.free.is_empty());
+        assert_eq!(
+            table.indices.get(&TileTransformUniform::IDENTITY.bits()),
+            Some(&0)
+        );
+    }
+}
@@ -0,0 +1,57 @@
+use bevy::{prelude::*, utils::HashSet};
+
+use crate::{
+    chunk::{ChunkData, ChunkEntities},
+    TileHighlight, TileHighlights, TileMap,
+};
+
+/// The highlighted tiles of a chunk, by their index in the chunk in ascending order.
+#[derive(Debug, Default, Clone, Component)]
+pub struct ChunkHighlights(pub(crate) Vec<(u32, TileHighlight)>);
+
+/// Copies the changed `TileHighlights` to the chunks they are in.
+pub(crate) fn sync_chunk_highlights(
+    mut tile_maps: Query<(Entity, &TileMap, &ChunkEntities, &mut TileHighlights)>,
+    new_chunks: Query<(Entity, &Parent), Added<ChunkData>>,
+    mut chunks: Query<(&ChunkData, &mut ChunkHighlights)>,
+) {
+    let mut updated: HashSet<(Entity, Entity)> = new_chunks
+        .iter()
+        .map(|(chunk, tile_map)| (chunk, tile_map.0))
+        .collect();
+
+    for (entity, tile_map, chunk_entities, mut highlights) in tile_maps.iter_mut() {
+        if highlights.dirty.is_empty() {
+            continue;
+        }
+
+        for coord in std::mem::take(&mut highlights.dirty) {
+            if let Some(chunk) = chunk_entities.get(&tile_map.coord_to_chunk_coord(coord)) {
+                updated.insert((*chunk, entity));
+            }
+        }
+    }
+
+    for (chunk, tile_map) in updated {
+        let (tile_map, highlights) =
+            if let Ok((_, tile_map, _, highlights)) = tile_maps.get(tile_map) {
+                (tile_map, highlights)
+            } else {
+                continue;
+            };
+        let (chunk_data, mut chunk_highlights) = if let Ok(chunk) = chunks.get_mut(chunk) {
+            chunk
+        } else {
+            continue;
+        };
+
+        let tiles: Vec<(u32, TileHighlight)> = tile_map
+            .chunk_tile_coords(chunk_data.chunk_coord)
+            .filter_map(|(index, coord)| Some((index as u32, *highlights.get(coord)?)))
+            .collect();
+
+        if chunk_highlights.0 != tiles {
+            chunk_highlights.0 = tiles;
+        }
+    }
+}
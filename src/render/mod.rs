@@ -1,6 +1,15 @@
+#[cfg(feature = "debug")]
+pub(crate) mod debug;
+mod encoding;
+mod highlight;
+mod pool;
 mod tile_sheet;
 
-use std::cmp::Ordering;
+use std::{
+    cmp::Ordering,
+    mem::size_of,
+    sync::{Arc, Mutex},
+};
 
 use bevy::{
     core::FloatOrd,
@@ -11,21 +20,56 @@ use bevy::{
     },
     prelude::*,
     render::{
-        render_asset::RenderAssets,
+        render_asset::{PrepareAssetLabel, RenderAssetPlugin, RenderAssets},
         render_phase::*,
-        render_resource::{std140::AsStd140, std430::AsStd430, *},
+        render_resource::{std140::AsStd140, *},
         renderer::{RenderDevice, RenderQueue},
         texture::BevyDefault,
         view::{ViewUniform, ViewUniformOffset, ViewUniforms},
-        RenderWorld,
+        RenderApp, RenderStage, RenderWorld,
     },
     utils::{HashMap, HashSet},
 };
 use bytemuck::{Pod, Zeroable};
 
-use crate::{chunk::ChunkData, Tile, TileKind};
-
-pub use tile_sheet::TileSheet;
+use crate::{chunk::ChunkData, Tile, TileHighlight, TileSheet};
+
+use encoding::{HighlightUniform, TileTransformUniform, TileUniform};
+pub(crate) use highlight::ChunkHighlights;
+use pool::{PoolKey, TilePools};
+
+/// Draws the chunks of every `TileMap`.
+pub struct TileMapRenderPlugin;
+
+impl Plugin for TileMapRenderPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ChunkShader>()
+            .add_plugin(RenderAssetPlugin::<TileSheet>::with_prepare_asset_label(
+                PrepareAssetLabel::PreAssetPrepare,
+            ))
+            .add_asset::<TileSheet>()
+            // Runs after the chunks spawned in `PostUpdate` exist.
+            .add_system_to_stage(CoreStage::Last, highlight::sync_chunk_highlights);
+
+        let shader = app.world.get_resource::<ChunkShader>().unwrap().clone();
+        if let Ok(render_app) = app.get_sub_app_mut(RenderApp) {
+            render_app
+                .insert_resource(shader)
+                .init_resource::<TileMapPipeline>()
+                .init_resource::<SpecializedRenderPipelines<TileMapPipeline>>()
+                .init_resource::<TileMapMeta>()
+                .init_resource::<ExtractedChunks>()
+                .init_resource::<TilePools>()
+                .init_resource::<TileMapGlobals>()
+                .add_render_command::<Transparent2d, DrawChunk>()
+                .add_system_to_stage(RenderStage::Extract, extract_chunks)
+                .add_system_to_stage(RenderStage::Extract, extract_time)
+                .add_system_to_stage(RenderStage::Prepare, prepare_tiles)
+                .add_system_to_stage(RenderStage::Prepare, prepare_globals)
+                .add_system_to_stage(RenderStage::Queue, queue_chunks);
+        };
+    }
+}
 
 #[derive(Clone)]
 pub struct ChunkShader(Handle<Shader>);
@@ -51,30 +95,66 @@ impl FromWorld for TileMapPipeline {
         let render_device = world.resource::<RenderDevice>();
 
         let view_layout = render_device.create_bind_group_layout(&BindGroupLayoutDescriptor {
-            entries: &[BindGroupLayoutEntry {
-                binding: 0,
-                visibility: ShaderStages::VERTEX,
-                ty: BindingType::Buffer {
-                    ty: BufferBindingType::Uniform,
-                    has_dynamic_offset: true,
-                    min_binding_size: BufferSize::new(ViewUniform::std140_size_static() as u64),
+            entries: &[
+                BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::VERTEX,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Uniform,
+                        has_dynamic_offset: true,
+                        min_binding_size: BufferSize::new(ViewUniform::std140_size_static() as u64),
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: BufferSize::new(
+                            GlobalsUniform::std140_size_static() as u64
+                        ),
+                    },
+                    count: None,
                 },
-                count: None,
-            }],
+            ],
             label: Some("TileMap::View::Layout"),
         });
 
         let tiles_layout = render_device.create_bind_group_layout(&BindGroupLayoutDescriptor {
-            entries: &[BindGroupLayoutEntry {
-                binding: 0,
-                visibility: ShaderStages::FRAGMENT,
-                ty: BindingType::Buffer {
-                    ty: BufferBindingType::Storage { read_only: true },
-                    has_dynamic_offset: false,
-                    min_binding_size: BufferSize::new(TileUniform::std430_size_static() as u64),
+            entries: &[
+                BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: BufferSize::new(size_of::<TileUniform>() as u64),
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: BufferSize::new(size_of::<TileTransformUniform>() as u64),
+                    },
+                    count: None,
                 },
-                count: None,
-            }],
+                BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: BufferSize::new(size_of::<HighlightUniform>() as u64),
+                    },
+                    count: None,
+                },
+            ],
             label: Some("TileMap::Tiles::Layout"),
         });
 
@@ -97,6 +177,28 @@ impl FromWorld for TileMapPipeline {
                         ty: BindingType::Sampler(SamplerBindingType::Filtering),
                         count: None,
                     },
+                    // Animations
+                    BindGroupLayoutEntry {
+                        binding: 2,
+                        visibility: ShaderStages::FRAGMENT,
+                        ty: BindingType::Buffer {
+                            ty: BufferBindingType::Storage { read_only: true },
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    // Animation frames
+                    BindGroupLayoutEntry {
+                        binding: 3,
+                        visibility: ShaderStages::FRAGMENT,
+                        ty: BindingType::Buffer {
+                            ty: BufferBindingType::Storage { read_only: true },
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
                 ],
                 label: Some("TileMap::Texture::Sampler::Layout"),
             });
@@ -185,28 +287,72 @@ impl SpecializedRenderPipeline for TileMapPipeline {
 }
 
 pub struct ExtractedChunk {
-    index: usize,
-    data: Vec<Tile>,
+    entity: Entity,
     chunk_size: UVec2,
     tile_size: UVec2,
     tile_sheet_handle: Handle<TileSheet>,
     transform: GlobalTransform,
+    // Seconds since the tiles of the chunk last changed.
+    since_changed: f32,
 }
 
+type ChunkUpdate = (Entity, PoolKey, Vec<Tile>, Vec<(u32, TileHighlight)>);
+
 #[derive(Default)]
 pub struct ExtractedChunks {
     chunks: Vec<ExtractedChunk>,
+    // Only the chunks whose tiles or highlights changed since the last extract, visible or not.
+    updated: Vec<ChunkUpdate>,
+    // Every chunk that still exists, so the GPU data of despawned chunks can be dropped.
+    alive: HashSet<Entity>,
+    // When the tiles of each chunk last changed, in seconds since startup.
+    changed_at: HashMap<Entity, f64>,
+}
+
+/// Measurements of the render world, shared with the main world by the
+/// `TileMapDiagnosticsPlugin`.
+#[derive(Clone, Default)]
+pub struct RenderStats(Arc<Mutex<RenderCounts>>);
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct RenderCounts {
+    pub(crate) extracted_chunks: usize,
+    pub(crate) uploaded_bytes: u64,
+    pub(crate) draw_calls: usize,
 }
 
+impl RenderStats {
+    pub(crate) fn get(&self) -> RenderCounts {
+        *self.0.lock().unwrap()
+    }
+
+    fn update(&self, update: impl FnOnce(&mut RenderCounts)) {
+        update(&mut self.0.lock().unwrap());
+    }
+}
+
+#[allow(clippy::type_complexity)]
 pub fn extract_chunks(
+    time: Res<Time>,
+    render_stats: Option<Res<RenderStats>>,
     images: Res<Assets<Image>>,
     mut image_event_reader: EventReader<AssetEvent<Image>>,
     mut render_world: ResMut<RenderWorld>,
     mut tile_sheets: ResMut<Assets<TileSheet>>,
-    chunks: Query<(&ComputedVisibility, &ChunkData, &GlobalTransform)>,
+    chunks: Query<(
+        Entity,
+        &ComputedVisibility,
+        &ChunkData,
+        ChangeTrackers<ChunkData>,
+        &ChunkHighlights,
+        ChangeTrackers<ChunkHighlights>,
+        &GlobalTransform,
+    )>,
 ) {
     let mut extracted_chunks = render_world.resource_mut::<ExtractedChunks>();
     extracted_chunks.chunks.clear();
+    extracted_chunks.updated.clear();
+    extracted_chunks.alive.clear();
 
     let mut updated_images = HashSet::new();
     for event in image_event_reader.iter() {
@@ -219,79 +365,123 @@ pub fn extract_chunks(
     }
 
     let mut updated_tile_sheets = HashSet::new();
-    for (index, (visibility, chunk_data, transform)) in chunks.iter().enumerate() {
+    for (
+        entity,
+        visibility,
+        chunk_data,
+        chunk_tracker,
+        highlights,
+        highlights_tracker,
+        transform,
+    ) in chunks.iter()
+    {
+        extracted_chunks.alive.insert(entity);
+        if chunk_tracker.is_changed() {
+            extracted_chunks
+                .changed_at
+                .insert(entity, time.seconds_since_startup());
+        }
+        if chunk_tracker.is_changed() || highlights_tracker.is_changed() {
+            extracted_chunks.updated.push((
+                entity,
+                PoolKey {
+                    tile_sheet: chunk_data.tile_sheet().as_weak(),
+                    chunk_size: chunk_data.chunk_size(),
+                },
+                chunk_data.tiles().clone(),
+                highlights.0.clone(),
+            ));
+        }
+
         if !visibility.is_visible {
             continue;
         }
 
         if updated_tile_sheets.insert(chunk_data.tile_sheet().as_weak::<TileSheet>()) {
-            if let Some(tile_sheet) = tile_sheets.get_mut(chunk_data.tile_sheet()) {
-                tile_sheet.update_images(&images, &updated_images);
+            // `get_mut` marks the tile sheet as modified, which uploads it again.
+            let needs_update = matches!(
+                tile_sheets.get(chunk_data.tile_sheet()),
+                Some(tile_sheet) if tile_sheet.needs_update(&updated_images)
+            );
+            if needs_update {
+                if let Some(tile_sheet) = tile_sheets.get_mut(chunk_data.tile_sheet()) {
+                    if let Err(err) = tile_sheet.update_images(&images, &updated_images) {
+                        error!("{}", err);
+                    }
+                }
             }
         }
 
+        let since_changed = extracted_chunks
+            .changed_at
+            .get(&entity)
+            .map_or(f32::MAX, |changed_at| {
+                (time.seconds_since_startup() - changed_at) as f32
+            });
         extracted_chunks.chunks.push(ExtractedChunk {
-            index,
-            data: chunk_data.tiles().clone(),
+            entity,
             chunk_size: chunk_data.chunk_size(),
             tile_size: chunk_data.tile_size(),
             tile_sheet_handle: chunk_data.tile_sheet().as_weak(),
-            transform: transform.clone(),
+            transform: *transform,
+            since_changed,
         });
     }
-}
 
-#[derive(Clone, Copy, AsStd430)]
-#[repr(C)]
-pub struct TileUniform {
-    idx: i32,
-    // FIXME: When using Mat3 it tries to write Mat4. It might be due to bugs with some drivers?
-    //        just using Mat4 for now due to it.
-    transform: Mat4,
-    mask_color: [f32; 4],
-}
+    let ExtractedChunks {
+        alive, changed_at, ..
+    } = &mut *extracted_chunks;
+    changed_at.retain(|entity, _| alive.contains(entity));
 
-impl TileUniform {
-    const DISCARD: TileUniform = TileUniform {
-        idx: -2,
-        transform: Mat4::IDENTITY,
-        mask_color: [1.0; 4],
-    };
+    if let Some(render_stats) = render_stats {
+        let extracted = extracted_chunks.chunks.len();
+        render_stats.update(|counts| counts.extracted_chunks = extracted);
+    }
 }
 
-impl From<&Tile> for TileUniform {
-    fn from(tile: &Tile) -> Self {
-        match &tile.kind {
-            Some(TileKind::Color { color, transform }) => TileUniform {
-                idx: -1,
-                transform: transform.into(),
-                mask_color: color.as_rgba_f32(),
-            },
-            Some(TileKind::Sprite {
-                idx,
-                transform,
-                mask_color,
-            }) => TileUniform {
-                idx: *idx as i32,
-                transform: transform.into(),
-                mask_color: mask_color.as_rgba_f32(),
-            },
-            None => TileUniform::DISCARD,
-        }
-    }
+// The debug fields stay zero, which draws nothing, without the `TileMapDebugPlugin`.
+#[derive(Clone, Copy, Default, AsStd140)]
+pub struct GlobalsUniform {
+    time: f32,
+    dirty_flash_duration: f32,
+    grid_color: Vec4,
+    chunk_border_color: Vec4,
+    dirty_flash_color: Vec4,
 }
 
 #[derive(Default)]
-pub struct TileUniforms(HashMap<usize, StorageBuffer<TileUniform>>);
+pub struct TileMapGlobals {
+    globals: GlobalsUniform,
+    uniform: UniformVec<GlobalsUniform>,
+}
 
-#[derive(Component)]
-pub struct TilesBindGroup(BindGroup);
+// Wrapping the time keeps enough f32 precision for frame durations. Animations that don't evenly
+// divide it skip once an hour.
+pub(crate) const TIME_WRAP_SECONDS: f64 = 3600.0;
+
+pub fn extract_time(time: Res<Time>, mut render_world: ResMut<RenderWorld>) {
+    render_world.resource_mut::<TileMapGlobals>().globals.time =
+        (time.seconds_since_startup() % TIME_WRAP_SECONDS) as f32;
+}
+
+pub fn prepare_globals(
+    render_device: Res<RenderDevice>,
+    render_queue: Res<RenderQueue>,
+    mut globals: ResMut<TileMapGlobals>,
+) {
+    let uniform = globals.globals;
+    globals.uniform.clear();
+    globals.uniform.push(uniform);
+    globals.uniform.write_buffer(&render_device, &render_queue);
+}
 
 pub fn prepare_tiles(
     render_device: Res<RenderDevice>,
     render_queue: Res<RenderQueue>,
+    tile_map_pipeline: Res<TileMapPipeline>,
+    render_stats: Option<Res<RenderStats>>,
     mut extracted_chunks: ResMut<ExtractedChunks>,
-    mut tile_uniforms: ResMut<TileUniforms>,
+    mut tile_pools: ResMut<TilePools>,
 ) {
     extracted_chunks.chunks.sort_by(|a, b| {
         match a
@@ -300,35 +490,41 @@ pub fn prepare_tiles(
             .z
             .partial_cmp(&b.transform.translation.z)
         {
-            Some(Ordering::Equal) | None => a.index.cmp(&b.index),
+            Some(Ordering::Equal) | None => a.entity.cmp(&b.entity),
             Some(other) => other,
         }
     });
 
-    for (_, buffer) in &mut tile_uniforms.0 {
-        buffer.clear()
+    let alive = &extracted_chunks.alive;
+    tile_pools.retain(|entity| alive.contains(&entity));
+
+    for (entity, key, tiles, highlights) in extracted_chunks.updated.drain(..) {
+        tile_pools.update(entity, key, &tiles, &highlights);
     }
 
-    for chunk in &mut extracted_chunks.chunks {
-        let buffer = if let Some(buffer) = tile_uniforms.0.get_mut(&chunk.index) {
-            buffer
-        } else {
-            tile_uniforms
-                .0
-                .insert(chunk.index, StorageBuffer::default());
-            tile_uniforms.0.get_mut(&chunk.index).unwrap()
-        };
-        for tile in &chunk.data {
-            buffer.push(tile.into());
-        }
-        buffer.write_buffer(&render_device, &render_queue);
+    let uploaded_bytes = tile_pools.write_buffers(
+        &render_device,
+        &render_queue,
+        &tile_map_pipeline.tiles_layout,
+    );
+    if let Some(render_stats) = render_stats {
+        render_stats.update(|counts| counts.uploaded_bytes = uploaded_bytes);
     }
 }
 
+/// The chunks drawn by one instanced draw call.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct BatchKey {
+    pool: PoolKey,
+    z: FloatOrd,
+}
+
 pub struct TileMapMeta {
     view_bind_group: Option<BindGroup>,
     // Chunk size to index vec
     index_buffers: HashMap<UVec2, BufferVec<u16>>,
+    // Kept between frames, so the buffers are only reallocated when a batch grows.
+    instance_buffers: HashMap<BatchKey, BufferVec<ChunkInstance>>,
 }
 
 impl Default for TileMapMeta {
@@ -336,6 +532,7 @@ impl Default for TileMapMeta {
         Self {
             view_bind_group: None,
             index_buffers: HashMap::default(),
+            instance_buffers: HashMap::default(),
         }
     }
 }
@@ -346,11 +543,16 @@ pub struct ChunkInstance {
     transform: Mat4,
     chunk_size: UVec2,
     tile_size: UVec2,
+    // Where the tiles of the chunk start in the tile buffer of its pool.
+    tile_offset: u32,
+    since_changed: f32,
+    // The first highlight of the chunk in the highlight buffer of its pool, and how many it has.
+    highlights: [u32; 2],
 }
 
 impl ChunkInstance {
     fn vertex_buffer_layout() -> VertexBufferLayout {
-        VertexBufferLayout::from_vertex_formats(
+        let mut layout = VertexBufferLayout::from_vertex_formats(
             VertexStepMode::Instance,
             [
                 // transform
@@ -362,17 +564,23 @@ impl ChunkInstance {
                 VertexFormat::Uint32x2,
                 // tile_size
                 VertexFormat::Uint32x2,
+                // tile_offset
+                VertexFormat::Uint32,
+                // since_changed
+                VertexFormat::Float32,
+                // highlights
+                VertexFormat::Uint32x2,
             ],
-        )
+        );
+        layout.array_stride = size_of::<ChunkInstance>() as u64;
+        layout
     }
 }
 
 #[derive(Component)]
-pub struct ChunkInstanceBuffer(BufferVec<ChunkInstance>);
-
-#[derive(Component)]
-pub struct ChunkInstanceData {
-    chunk_size: UVec2,
+pub struct ChunkBatch {
+    key: BatchKey,
+    instance_count: u32,
 }
 
 #[allow(clippy::too_many_arguments)]
@@ -386,93 +594,130 @@ pub fn queue_chunks(
     tile_map_pipeline: Res<TileMapPipeline>,
     msaa: Res<Msaa>,
     extracted_chunks: Res<ExtractedChunks>,
-    tile_uniforms: Res<TileUniforms>,
+    tile_pools: Res<TilePools>,
+    globals: Res<TileMapGlobals>,
+    render_stats: Option<Res<RenderStats>>,
     mut pipelines: ResMut<SpecializedRenderPipelines<TileMapPipeline>>,
     mut pipeline_cache: ResMut<PipelineCache>,
     mut views: Query<&mut RenderPhase<Transparent2d>>,
 ) {
-    if let Some(view_binding) = view_uniforms.uniforms.binding() {
+    if let (Some(view_binding), Some(globals_binding)) =
+        (view_uniforms.uniforms.binding(), globals.uniform.binding())
+    {
         let chunk_meta = &mut chunk_meta;
 
         chunk_meta.view_bind_group = Some(render_device.create_bind_group(&BindGroupDescriptor {
-            entries: &[BindGroupEntry {
-                binding: 0,
-                resource: view_binding,
-            }],
+            entries: &[
+                BindGroupEntry {
+                    binding: 0,
+                    resource: view_binding,
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: globals_binding,
+                },
+            ],
             label: Some("TileMap::ViewBindGroup"),
             layout: &tile_map_pipeline.view_layout,
         }));
 
-        let draw_chunk_function = draw_functions.read().get_id::<DrawChunk>().unwrap();
-        let key = TileMapPipelineKey::from_msaa_samples(msaa.samples);
-        let pipeline = pipelines.specialize(&mut pipeline_cache, &tile_map_pipeline, key);
+        for instance_buffer in chunk_meta.instance_buffers.values_mut() {
+            instance_buffer.clear();
+        }
 
-        for mut transparent_phase in views.iter_mut() {
-            let extracted_chunks = &extracted_chunks.chunks;
-            transparent_phase.items.reserve(extracted_chunks.len());
-
-            for chunk in extracted_chunks.iter() {
-                // Init index buffer if its not already ready
-                if chunk_meta.index_buffers.get(&chunk.chunk_size).is_none() {
-                    let mut buffer = BufferVec::new(BufferUsages::INDEX);
-
-                    const INDICES: [u16; 6] = [0, 3, 1, 0, 2, 3];
-                    for tile_idx in 0..chunk.chunk_size.x * chunk.chunk_size.y {
-                        for index in INDICES {
-                            buffer.push(index + (4 * tile_idx) as u16);
-                        }
-                    }
+        for chunk in extracted_chunks.chunks.iter() {
+            // Init index buffer if its not already ready
+            if chunk_meta.index_buffers.get(&chunk.chunk_size).is_none() {
+                let mut buffer = BufferVec::new(BufferUsages::INDEX);
 
-                    buffer.write_buffer(&render_device, &render_queue);
-                    chunk_meta.index_buffers.insert(chunk.chunk_size, buffer);
+                const INDICES: [u16; 6] = [0, 3, 1, 0, 2, 3];
+                for tile_idx in 0..chunk.chunk_size.x * chunk.chunk_size.y {
+                    for index in INDICES {
+                        buffer.push(index + (4 * tile_idx) as u16);
+                    }
                 }
 
-                let tiles_bind_group = if let Some(Some(tiles_binding)) = tile_uniforms
-                    .0
-                    .get(&chunk.index)
-                    .map(|buffer| buffer.binding())
-                {
-                    render_device.create_bind_group(&BindGroupDescriptor {
-                        entries: &[BindGroupEntry {
-                            binding: 0,
-                            resource: tiles_binding,
-                        }],
-                        label: Some("TileMap::TilesBindGroup"),
-                        layout: &tile_map_pipeline.tiles_layout,
-                    })
-                } else {
-                    continue;
-                };
-
-                let mut instance_buffer = BufferVec::new(BufferUsages::VERTEX);
-                instance_buffer.push(ChunkInstance {
+                buffer.write_buffer(&render_device, &render_queue);
+                chunk_meta.index_buffers.insert(chunk.chunk_size, buffer);
+            }
+
+            let pool = PoolKey {
+                tile_sheet: chunk.tile_sheet_handle.clone_weak(),
+                chunk_size: chunk.chunk_size,
+            };
+            let (tile_offset, highlights) = if let Some(chunk_tiles) =
+                tile_pools.get(&pool).and_then(|pool| {
+                    Some((
+                        pool.tile_offset(chunk.entity)?,
+                        pool.highlight_range(chunk.entity),
+                    ))
+                }) {
+                chunk_tiles
+            } else {
+                continue;
+            };
+
+            chunk_meta
+                .instance_buffers
+                .entry(BatchKey {
+                    pool,
+                    z: FloatOrd(chunk.transform.translation.z),
+                })
+                .or_insert_with(|| BufferVec::new(BufferUsages::VERTEX))
+                .push(ChunkInstance {
                     transform: chunk.transform.compute_matrix(),
                     chunk_size: chunk.chunk_size,
                     tile_size: chunk.tile_size,
+                    tile_offset,
+                    since_changed: chunk.since_changed,
+                    highlights,
                 });
-                instance_buffer.write_buffer(&render_device, &render_queue);
+        }
 
-                let entity = commands
-                    .spawn_bundle((
-                        ChunkInstanceData {
-                            chunk_size: chunk.chunk_size,
-                        },
-                        ChunkInstanceBuffer(instance_buffer),
-                        TilesBindGroup(tiles_bind_group),
-                        chunk.tile_sheet_handle.as_weak::<TileSheet>(),
-                    ))
-                    .id();
-                let sort_key = FloatOrd(chunk.transform.translation.z);
+        // Batches that didn't get any chunks this frame are dropped.
+        chunk_meta
+            .instance_buffers
+            .retain(|_, instance_buffer| !instance_buffer.is_empty());
+
+        let draw_chunk_function = draw_functions.read().get_id::<DrawChunk>().unwrap();
+        let key = TileMapPipelineKey::from_msaa_samples(msaa.samples);
+        let pipeline = pipelines.specialize(&mut pipeline_cache, &tile_map_pipeline, key);
+
+        let mut batches = Vec::with_capacity(chunk_meta.instance_buffers.len());
+        for (key, instance_buffer) in chunk_meta.instance_buffers.iter_mut() {
+            instance_buffer.write_buffer(&render_device, &render_queue);
 
+            let entity = commands
+                .spawn_bundle((
+                    ChunkBatch {
+                        key: key.clone(),
+                        instance_count: instance_buffer.len() as u32,
+                    },
+                    key.pool.tile_sheet.clone_weak(),
+                ))
+                .id();
+            batches.push((entity, key.z));
+        }
+
+        let mut draw_calls = 0;
+        for mut transparent_phase in views.iter_mut() {
+            transparent_phase.items.reserve(batches.len());
+            draw_calls += batches.len();
+
+            for (entity, sort_key) in batches.iter() {
                 transparent_phase.add(Transparent2d {
                     draw_function: draw_chunk_function,
                     pipeline,
-                    entity,
-                    sort_key,
+                    entity: *entity,
+                    sort_key: *sort_key,
                     batch_range: None,
                 });
             }
         }
+
+        if let Some(render_stats) = render_stats {
+            render_stats.update(|counts| counts.draw_calls = draw_calls);
+        }
     }
 }
 
@@ -509,18 +754,26 @@ impl<const I: usize> EntityRenderCommand for SetChunkViewBindGroup<I> {
 pub struct SetChunkTilesBindGroup<const I: usize>;
 
 impl<const I: usize> EntityRenderCommand for SetChunkTilesBindGroup<I> {
-    type Param = SQuery<Read<TilesBindGroup>>;
+    type Param = (SRes<TilePools>, SQuery<Read<ChunkBatch>>);
 
     #[inline]
     fn render<'w>(
         _view: Entity,
         item: Entity,
-        tiles_query: SystemParamItem<'w, '_, Self::Param>,
+        (tile_pools, batch_query): SystemParamItem<'w, '_, Self::Param>,
         pass: &mut TrackedRenderPass<'w>,
     ) -> RenderCommandResult {
-        let tiles_bind_group = tiles_query.get_inner(item).unwrap();
-        pass.set_bind_group(I, &tiles_bind_group.0, &[]);
-        RenderCommandResult::Success
+        let batch = batch_query.get_inner(item).unwrap();
+        if let Some(bind_group) = tile_pools
+            .into_inner()
+            .get(&batch.key.pool)
+            .and_then(|pool| pool.bind_group())
+        {
+            pass.set_bind_group(I, bind_group, &[]);
+            RenderCommandResult::Success
+        } else {
+            RenderCommandResult::Failure
+        }
     }
 }
 
@@ -553,30 +806,36 @@ impl<const I: usize> EntityRenderCommand for SetChunkTextureBindGroup<I> {
 pub struct DrawChunkCommand;
 
 impl EntityRenderCommand for DrawChunkCommand {
-    type Param = (
-        SRes<TileMapMeta>,
-        SQuery<(Read<ChunkInstanceBuffer>, Read<ChunkInstanceData>)>,
-    );
+    type Param = (SRes<TileMapMeta>, SQuery<Read<ChunkBatch>>);
 
     #[inline]
     fn render<'w>(
         _view: Entity,
         item: Entity,
-        (meta, chunk_query): SystemParamItem<'w, '_, Self::Param>,
+        (meta, batch_query): SystemParamItem<'w, '_, Self::Param>,
         pass: &mut TrackedRenderPass<'w>,
     ) -> RenderCommandResult {
-        let (buffer, data) = chunk_query.get_inner(item).unwrap();
-        pass.set_vertex_buffer(0, buffer.0.buffer().unwrap().slice(..));
+        let batch = batch_query.get_inner(item).unwrap();
+        let meta = meta.into_inner();
+        let chunk_size = batch.key.pool.chunk_size;
 
-        pass.set_index_buffer(
-            meta.into_inner().index_buffers[&data.chunk_size]
+        pass.set_vertex_buffer(
+            0,
+            meta.instance_buffers[&batch.key]
                 .buffer()
                 .unwrap()
                 .slice(..),
+        );
+        pass.set_index_buffer(
+            meta.index_buffers[&chunk_size].buffer().unwrap().slice(..),
             0,
             IndexFormat::Uint16,
         );
-        pass.draw_indexed(0..(data.chunk_size.x * data.chunk_size.y * 6), 0, 0..1);
+        pass.draw_indexed(
+            0..(chunk_size.x * chunk_size.y * 6),
+            0,
+            0..batch.instance_count,
+        );
 
         RenderCommandResult::Success
     }
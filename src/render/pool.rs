@@ -0,0 +1,474 @@
+use std::mem::{size_of, size_of_val};
+
+use bevy::{
+    prelude::*,
+    render::{
+        render_resource::*,
+        renderer::{RenderDevice, RenderQueue},
+    },
+    utils::HashMap,
+};
+
+use super::{
+    encoding::{HighlightUniform, TileUniform, TransformTable},
+    TileSheet,
+};
+use crate::{Tile, TileHighlight};
+
+/// Chunks can only share a pool, and so a draw call, if they use the same tile sheet and chunk
+/// size.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct PoolKey {
+    pub tile_sheet: Handle<TileSheet>,
+    pub chunk_size: UVec2,
+}
+
+/// The tiles of many chunks in one storage buffer. Every chunk gets a fixed size slot, which is
+/// only written again when the chunk changes.
+pub struct TilePool {
+    tiles_per_chunk: usize,
+    slots: HashMap<Entity, u32>,
+    free_slots: Vec<u32>,
+    // A copy of the tile buffer, so it can be grown without reading it back.
+    tiles: Vec<TileUniform>,
+    dirty_slots: Vec<u32>,
+    transforms: TransformTable,
+    // Only the chunks with highlighted tiles, most chunks don't have any.
+    highlights: HashMap<Entity, Vec<HighlightUniform>>,
+    // Where the highlights of each chunk start in the highlight buffer, and how many it has.
+    highlight_ranges: HashMap<Entity, [u32; 2]>,
+    highlights_changed: bool,
+    tile_buffer: GrowableBuffer,
+    transform_buffer: GrowableBuffer,
+    highlight_buffer: GrowableBuffer,
+    bind_group: Option<BindGroup>,
+}
+
+impl TilePool {
+    pub fn new(chunk_size: UVec2) -> Self {
+        TilePool {
+            tiles_per_chunk: (chunk_size.x * chunk_size.y) as usize,
+            slots: HashMap::default(),
+            free_slots: Vec::new(),
+            tiles: Vec::new(),
+            dirty_slots: Vec::new(),
+            transforms: TransformTable::default(),
+            highlights: HashMap::default(),
+            highlight_ranges: HashMap::default(),
+            highlights_changed: true,
+            tile_buffer: GrowableBuffer::default(),
+            transform_buffer: GrowableBuffer::default(),
+            highlight_buffer: GrowableBuffer::default(),
+            bind_group: None,
+        }
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.slots.is_empty()
+    }
+
+    #[inline]
+    pub fn bind_group(&self) -> Option<&BindGroup> {
+        self.bind_group.as_ref()
+    }
+
+    /// The index of the first tile of the chunk in the tile buffer.
+    #[inline]
+    pub fn tile_offset(&self, entity: Entity) -> Option<u32> {
+        self.slots
+            .get(&entity)
+            .map(|slot| *slot * self.tiles_per_chunk as u32)
+    }
+
+    /// The first highlight of the chunk in the highlight buffer and how many it has.
+    #[inline]
+    pub fn highlight_range(&self, entity: Entity) -> [u32; 2] {
+        self.highlight_ranges
+            .get(&entity)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    pub fn update(&mut self, entity: Entity, tiles: &[Tile], highlights: &[(u32, TileHighlight)]) {
+        let slot = if let Some(slot) = self.slots.get(&entity) {
+            *slot
+        } else {
+            let slot = self.free_slots.pop().unwrap_or_else(|| {
+                let slot = (self.tiles.len() / self.tiles_per_chunk) as u32;
+                let len = self.tiles.len() + self.tiles_per_chunk;
+                self.tiles.resize(len, TileUniform::default());
+                slot
+            });
+            self.slots.insert(entity, slot);
+            slot
+        };
+
+        let range = self.slot_range(slot);
+        // Acquire before releasing, so transforms the chunk keeps using stay at the same index.
+        let old_tiles = self.tiles[range.clone()].to_vec();
+        self.transforms
+            .encode(tiles, &mut self.tiles[range.clone()]);
+        self.transforms.release_tiles(&old_tiles);
+
+        let first_tile = range.start as u32;
+        self.set_highlights(
+            entity,
+            highlights
+                .iter()
+                .map(|(index, highlight)| HighlightUniform::new(first_tile + index, highlight))
+                .collect(),
+        );
+
+        self.dirty_slots.push(slot);
+    }
+
+    pub fn remove(&mut self, entity: Entity) {
+        if let Some(slot) = self.slots.remove(&entity) {
+            let range = self.slot_range(slot);
+            self.transforms.release_tiles(&self.tiles[range.clone()]);
+            self.tiles[range].fill(TileUniform::default());
+            self.free_slots.push(slot);
+            self.set_highlights(entity, Vec::new());
+        }
+    }
+
+    fn set_highlights(&mut self, entity: Entity, highlights: Vec<HighlightUniform>) {
+        if self.highlights.get(&entity).map_or(&[][..], |old| old) == highlights.as_slice() {
+            return;
+        }
+
+        if highlights.is_empty() {
+            self.highlights.remove(&entity);
+        } else {
+            self.highlights.insert(entity, highlights);
+        }
+        self.highlights_changed = true;
+    }
+
+    /// Returns how many bytes were written.
+    pub fn write_buffers(
+        &mut self,
+        render_device: &RenderDevice,
+        render_queue: &RenderQueue,
+        layout: &BindGroupLayout,
+    ) -> u64 {
+        let mut reallocated = false;
+        let mut written = 0;
+
+        if self.tile_buffer.reserve_and_write(
+            &self.tiles,
+            "TileMap::Tiles",
+            render_device,
+            render_queue,
+        ) {
+            reallocated = true;
+            written += size_of_val(self.tiles.as_slice()) as u64;
+        } else if let Some(tile_buffer) = &self.tile_buffer.buffer {
+            for slot in self.dirty_slots.iter() {
+                let range = self.slot_range(*slot);
+                let offset = (range.start * size_of::<TileUniform>()) as u64;
+                written += (range.len() * size_of::<TileUniform>()) as u64;
+                render_queue.write_buffer(
+                    tile_buffer,
+                    offset,
+                    bytemuck::cast_slice(&self.tiles[range]),
+                );
+            }
+        }
+        self.dirty_slots.clear();
+
+        if self.transforms.take_changed() {
+            written += size_of_val(self.transforms.transforms()) as u64;
+            if self.transform_buffer.reserve_and_write(
+                self.transforms.transforms(),
+                "TileMap::TileTransforms",
+                render_device,
+                render_queue,
+            ) {
+                reallocated = true;
+            } else {
+                render_queue.write_buffer(
+                    self.transform_buffer.buffer.as_ref().unwrap(),
+                    0,
+                    bytemuck::cast_slice(self.transforms.transforms()),
+                );
+            }
+        }
+
+        if self.highlights_changed {
+            self.highlights_changed = false;
+            self.highlight_ranges.clear();
+            let mut highlights = Vec::new();
+            for (entity, chunk_highlights) in self.highlights.iter() {
+                self.highlight_ranges.insert(
+                    *entity,
+                    [highlights.len() as u32, chunk_highlights.len() as u32],
+                );
+                highlights.extend_from_slice(chunk_highlights);
+            }
+            // Bindings can't be empty.
+            if highlights.is_empty() {
+                highlights.push(HighlightUniform::default());
+            }
+
+            written += size_of_val(highlights.as_slice()) as u64;
+            if self.highlight_buffer.reserve_and_write(
+                &highlights,
+                "TileMap::TileHighlights",
+                render_device,
+                render_queue,
+            ) {
+                reallocated = true;
+            } else {
+                render_queue.write_buffer(
+                    self.highlight_buffer.buffer.as_ref().unwrap(),
+                    0,
+                    bytemuck::cast_slice(&highlights),
+                );
+            }
+        }
+
+        if reallocated || self.bind_group.is_none() {
+            self.bind_group = Some(
+                render_device.create_bind_group(&BindGroupDescriptor {
+                    entries: &[
+                        BindGroupEntry {
+                            binding: 0,
+                            resource: self
+                                .tile_buffer
+                                .buffer
+                                .as_ref()
+                                .unwrap()
+                                .as_entire_binding(),
+                        },
+                        BindGroupEntry {
+                            binding: 1,
+                            resource: self
+                                .transform_buffer
+                                .buffer
+                                .as_ref()
+                                .unwrap()
+                                .as_entire_binding(),
+                        },
+                        BindGroupEntry {
+                            binding: 2,
+                            resource: self
+                                .highlight_buffer
+                                .buffer
+                                .as_ref()
+                                .unwrap()
+                                .as_entire_binding(),
+                        },
+                    ],
+                    label: Some("TileMap::TilesBindGroup"),
+                    layout,
+                }),
+            );
+        }
+
+        written
+    }
+
+    #[inline]
+    fn slot_range(&self, slot: u32) -> std::ops::Range<usize> {
+        let start = slot as usize * self.tiles_per_chunk;
+        start..start + self.tiles_per_chunk
+    }
+}
+
+/// A storage buffer that only gets reallocated when it's too small.
+#[derive(Default)]
+struct GrowableBuffer {
+    buffer: Option<Buffer>,
+    capacity: u64,
+}
+
+impl GrowableBuffer {
+    /// Reallocates the buffer with room to grow and writes all of `data` if it's too small for
+    /// it. Returns true if it did.
+    fn reserve_and_write<T: bytemuck::Pod>(
+        &mut self,
+        data: &[T],
+        label: &str,
+        render_device: &RenderDevice,
+        render_queue: &RenderQueue,
+    ) -> bool {
+        let size = size_of_val(data) as u64;
+        if self.buffer.is_some() && self.capacity >= size {
+            return false;
+        }
+
+        self.capacity = size.next_power_of_two().max(size_of::<T>() as u64);
+        let buffer = render_device.create_buffer(&BufferDescriptor {
+            label: Some(label),
+            size: self.capacity,
+            usage: BufferUsages::STORAGE | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        render_queue.write_buffer(&buffer, 0, bytemuck::cast_slice(data));
+        self.buffer = Some(buffer);
+        true
+    }
+}
+
+/// Every `TilePool`, and which pool each chunk is in.
+#[derive(Default)]
+pub struct TilePools {
+    pools: HashMap<PoolKey, TilePool>,
+    chunk_pools: HashMap<Entity, PoolKey>,
+}
+
+impl TilePools {
+    #[inline]
+    pub fn get(&self, key: &PoolKey) -> Option<&TilePool> {
+        self.pools.get(key)
+    }
+
+    pub fn update(
+        &mut self,
+        entity: Entity,
+        key: PoolKey,
+        tiles: &[Tile],
+        highlights: &[(u32, TileHighlight)],
+    ) {
+        // The tile sheet or chunk size of the chunk changed.
+        if matches!(self.chunk_pools.get(&entity), Some(old_key) if *old_key != key) {
+            self.remove(entity);
+        }
+
+        self.pools
+            .entry(key.clone())
+            .or_insert_with(|| TilePool::new(key.chunk_size))
+            .update(entity, tiles, highlights);
+        self.chunk_pools.insert(entity, key);
+    }
+
+    pub fn remove(&mut self, entity: Entity) {
+        if let Some(key) = self.chunk_pools.remove(&entity) {
+            if let Some(pool) = self.pools.get_mut(&key) {
+                pool.remove(entity);
+            }
+        }
+    }
+
+    /// Removes every chunk for which `keep` returns false.
+    pub fn retain(&mut self, mut keep: impl FnMut(Entity) -> bool) {
+        let removed: Vec<Entity> = self
+            .chunk_pools
+            .keys()
+            .copied()
+            .filter(|entity| !keep(*entity))
+            .collect();
+        for entity in removed {
+            self.remove(entity);
+        }
+    }
+
+    /// Returns how many bytes were written.
+    pub fn write_buffers(
+        &mut self,
+        render_device: &RenderDevice,
+        render_queue: &RenderQueue,
+        layout: &BindGroupLayout,
+    ) -> u64 {
+        self.pools.retain(|_, pool| !pool.is_empty());
+        self.pools
+            .values_mut()
+            .map(|pool| pool.write_buffers(render_device, render_queue, layout))
+            .sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CHUNK_SIZE: UVec2 = bevy::math::const_uvec2!([2, 2]);
+
+    fn key(chunk_size: UVec2) -> PoolKey {
+        PoolKey {
+            tile_sheet: Handle::default(),
+            chunk_size,
+        }
+    }
+
+    fn tiles() -> Vec<Tile> {
+        vec![Tile::default(); (CHUNK_SIZE.x * CHUNK_SIZE.y) as usize]
+    }
+
+    #[test]
+    fn chunks_get_their_own_slots() {
+        let mut pool = TilePool::new(CHUNK_SIZE);
+        pool.update(Entity::from_raw(0), &tiles(), &[]);
+        pool.update(Entity::from_raw(1), &tiles(), &[]);
+
+        assert_eq!(pool.tile_offset(Entity::from_raw(0)), Some(0));
+        assert_eq!(pool.tile_offset(Entity::from_raw(1)), Some(4));
+        assert_eq!(pool.tile_offset(Entity::from_raw(2)), None);
+        assert_eq!(pool.tiles.len(), 8);
+        assert_eq!(pool.dirty_slots, vec![0, 1]);
+    }
+
+    #[test]
+    fn updating_a_chunk_keeps_its_slot() {
+        let mut pool = TilePool::new(CHUNK_SIZE);
+        pool.update(Entity::from_raw(0), &tiles(), &[]);
+        pool.update(Entity::from_raw(1), &tiles(), &[]);
+        pool.dirty_slots.clear();
+
+        pool.update(Entity::from_raw(1), &tiles(), &[]);
+        assert_eq!(pool.tile_offset(Entity::from_raw(1)), Some(4));
+        assert_eq!(pool.tiles.len(), 8);
+        assert_eq!(pool.dirty_slots, vec![1]);
+    }
+
+    #[test]
+    fn slots_of_removed_chunks_are_reused() {
+        let mut pool = TilePool::new(CHUNK_SIZE);
+        pool.update(Entity::from_raw(0), &tiles(), &[]);
+        pool.update(Entity::from_raw(1), &tiles(), &[]);
+
+        pool.remove(Entity::from_raw(0));
+        assert_eq!(pool.tile_offset(Entity::from_raw(0)), None);
+        assert!(!pool.is_empty());
+
+        pool.update(Entity::from_raw(2), &tiles(), &[]);
+        assert_eq!(pool.tile_offset(Entity::from_raw(2)), Some(0));
+        assert_eq!(pool.tiles.len(), 8);
+
+        pool.remove(Entity::from_raw(1));
+        pool.remove(Entity::from_raw(2));
+        assert!(pool.is_empty());
+    }
+
+    #[test]
+    fn chunks_move_pools_when_their_key_changes() {
+        let mut pools = TilePools::default();
+        pools.update(Entity::from_raw(0), key(CHUNK_SIZE), &tiles(), &[]);
+        pools.update(Entity::from_raw(1), key(CHUNK_SIZE), &tiles(), &[]);
+
+        let other = key(UVec2::ONE);
+        pools.update(Entity::from_raw(0), other.clone(), &[Tile::default()], &[]);
+        let pool = pools.get(&key(CHUNK_SIZE)).unwrap();
+        assert_eq!(pool.tile_offset(Entity::from_raw(0)), None);
+        assert_eq!(pool.tile_offset(Entity::from_raw(1)), Some(4));
+        assert_eq!(
+            pools.get(&other).unwrap().tile_offset(Entity::from_raw(0)),
+            Some(0)
+        );
+    }
+
+    #[test]
+    fn retain_removes_despawned_chunks() {
+        let mut pools = TilePools::default();
+        pools.update(Entity::from_raw(0), key(CHUNK_SIZE), &tiles(), &[]);
+        pools.update(Entity::from_raw(1), key(CHUNK_SIZE), &tiles(), &[]);
+
+        pools.retain(|entity| entity != Entity::from_raw(0));
+        let pool = pools.get(&key(CHUNK_SIZE)).unwrap();
+        assert_eq!(pool.tile_offset(Entity::from_raw(0)), None);
+        assert_eq!(pool.tile_offset(Entity::from_raw(1)), Some(4));
+        assert_eq!(pool.free_slots, vec![0]);
+    }
+}
@@ -1,124 +1,221 @@
-use std::num::NonZeroU32;
+use std::num::{NonZeroU32, NonZeroU8};
 
 use bevy::{
     ecs::system::{lifetimeless::SRes, SystemParamItem},
-    math::const_uvec2,
-    prelude::*,
-    reflect::TypeUuid,
+    log::error,
+    math::UVec2,
     render::{
         render_asset::{PrepareAssetError, RenderAsset},
-        render_resource::*,
+        render_resource::{std430::AsStd430, *},
         renderer::{RenderDevice, RenderQueue},
-        texture::{BevyDefault, TextureFormatPixelInfo},
+        texture::TextureFormatPixelInfo,
     },
-    utils::HashSet,
 };
 
 use super::TileMapPipeline;
+use crate::{tile_sheet::convert, TileAnimationMode, TileFilter, TileSheet, TileSheetSampling};
 
-#[derive(Debug, Clone, TypeUuid)]
-#[uuid = "fd3a76be-60a3-4b67-a2da-8c987f65ae16"]
-pub struct TileSheet {
-    tile_sets: Vec<Handle<Image>>,
-    tile_size: UVec2,
-    tile_data: Vec<u8>,
-    array_count: u32,
-    format: Option<TextureFormat>,
+impl TileFilter {
+    fn as_gpu(&self) -> FilterMode {
+        match self {
+            TileFilter::Nearest => FilterMode::Nearest,
+            TileFilter::Linear => FilterMode::Linear,
+        }
+    }
 }
 
-impl TileSheet {
-    pub fn new(mut tile_sets: Vec<Handle<Image>>, tile_size: UVec2) -> Self {
-        tile_sets.sort();
-        tile_sets.dedup();
-
-        TileSheet {
-            tile_sets,
-            tile_size,
-            tile_data: Vec::new(),
-            array_count: 0,
-            format: None,
+impl TileSheetSampling {
+    fn anisotropy_clamp(&self) -> Option<NonZeroU8> {
+        if self.filter != TileFilter::Linear || self.anisotropy <= 1 {
+            return None;
         }
+        // Only powers of two up to 16 are valid.
+        let clamp = self.anisotropy.min(16);
+        NonZeroU8::new(1 << (7 - clamp.leading_zeros()))
     }
+}
+
+/// The channels of the formats mipmaps can be generated for.
+#[derive(Clone, Copy)]
+enum Channel {
+    U8,
+    U16,
+    F16,
+    F32,
+}
 
-    pub fn empty() -> TileSheet {
-        TileSheet {
-            tile_sets: Vec::new(),
-            tile_size: const_uvec2!([1, 1]),
-            tile_data: vec![0, 0, 0, 0],
-            array_count: 1,
-            format: Some(TextureFormat::bevy_default()),
+impl Channel {
+    fn of(format: TextureFormat) -> Option<Channel> {
+        match format {
+            TextureFormat::R8Unorm
+            | TextureFormat::Rg8Unorm
+            | TextureFormat::Rgba8Unorm
+            | TextureFormat::Rgba8UnormSrgb
+            | TextureFormat::Bgra8Unorm
+            | TextureFormat::Bgra8UnormSrgb => Some(Channel::U8),
+            TextureFormat::R16Unorm | TextureFormat::Rg16Unorm | TextureFormat::Rgba16Unorm => {
+                Some(Channel::U16)
+            }
+            TextureFormat::R16Float | TextureFormat::Rg16Float | TextureFormat::Rgba16Float => {
+                Some(Channel::F16)
+            }
+            TextureFormat::R32Float | TextureFormat::Rg32Float | TextureFormat::Rgba32Float => {
+                Some(Channel::F32)
+            }
+            _ => None,
         }
     }
 
-    pub fn update_images(
-        &mut self,
-        images: &Assets<Image>,
-        updated_images: &HashSet<Handle<Image>>,
-    ) {
-        if self
-            .tile_sets
-            .iter()
-            .any(|handle| updated_images.contains(handle))
-        {
-            let mut used_space = 0;
-            let mut format = None;
-
-            for image_handle in self.tile_sets.iter() {
-                if let Some(img) = images.get(image_handle) {
-                    let needed_space = img
-                        .data
-                        .len()
-                        .checked_sub(self.tile_data.len() - used_space);
-                    if let Some(needed_space) = needed_space {
-                        self.tile_data.extend(vec![0; needed_space]);
+    fn size(&self) -> usize {
+        match self {
+            Channel::U8 => 1,
+            Channel::U16 | Channel::F16 => 2,
+            Channel::F32 => 4,
+        }
+    }
+
+    /// The stored value of 1.0.
+    fn scale(&self) -> f32 {
+        match self {
+            Channel::U8 => 255.0,
+            Channel::U16 => 65535.0,
+            Channel::F16 | Channel::F32 => 1.0,
+        }
+    }
+
+    fn read(&self, bytes: &[u8]) -> f32 {
+        match self {
+            Channel::U8 => bytes[0] as f32,
+            Channel::U16 => u16::from_le_bytes([bytes[0], bytes[1]]) as f32,
+            Channel::F16 => convert::f16_to_f32(u16::from_le_bytes([bytes[0], bytes[1]])),
+            Channel::F32 => f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
+        }
+    }
+
+    fn write(&self, value: f32, bytes: &mut [u8]) {
+        match self {
+            Channel::U8 => bytes[0] = value.round() as u8,
+            Channel::U16 => bytes.copy_from_slice(&(value.round() as u16).to_le_bytes()),
+            Channel::F16 => bytes.copy_from_slice(&convert::f32_to_f16(value).to_le_bytes()),
+            Channel::F32 => bytes.copy_from_slice(&value.to_le_bytes()),
+        }
+    }
+}
+
+/// Appends a mip chain to every layer of `data`, in the layer by layer order
+/// `create_texture_with_data` expects. sRGB formats are filtered in linear space, and the colors
+/// of formats with four channels are weighted by alpha, so transparent pixels don't bleed in to
+/// them. Returns the data and the number of mip levels, `None` for formats without a `Channel`.
+fn generate_mipmaps(
+    data: &[u8],
+    size: UVec2,
+    layers: u32,
+    format: TextureFormat,
+) -> Option<(Vec<u8>, u32)> {
+    let channel = Channel::of(format)?;
+    let pixel_size = format.pixel_size();
+    let channels = pixel_size / channel.size();
+    let srgb = matches!(
+        format,
+        TextureFormat::Rgba8UnormSrgb | TextureFormat::Bgra8UnormSrgb
+    );
+    let has_alpha = channels == 4;
+    let mip_level_count = 32 - size.x.max(size.y).leading_zeros();
+    let layer_size = (size.x * size.y) as usize * pixel_size;
+
+    let mut out = Vec::with_capacity(data.len() * 4 / 3 + pixel_size * layers as usize);
+    for layer in data.chunks_exact(layer_size).take(layers as usize) {
+        let mut level = layer.to_vec();
+        let mut level_size = size;
+        out.extend_from_slice(&level);
+
+        for _ in 1..mip_level_count {
+            let next_size = (level_size / 2).max(UVec2::ONE);
+            let mut next = vec![0; (next_size.x * next_size.y) as usize * pixel_size];
+
+            for y in 0..next_size.y {
+                for x in 0..next_size.x {
+                    // A box filter over the 2x2 pixels, or fewer at odd edges.
+                    let mut sum = [0.0; 4];
+                    let mut weighted_sum = [0.0; 4];
+                    let mut alpha_sum = 0.0;
+                    let mut count = 0.0;
+                    for sy in (y * 2)..(y * 2 + 2).min(level_size.y) {
+                        for sx in (x * 2)..(x * 2 + 2).min(level_size.x) {
+                            let src = ((sy * level_size.x + sx) as usize) * pixel_size;
+                            let mut pixel = [0.0; 4];
+                            for (c, value) in pixel.iter_mut().enumerate().take(channels) {
+                                *value = channel.read(&level[src + c * channel.size()..])
+                                    / channel.scale();
+                                if srgb && c < 3 {
+                                    *value = convert::srgb_to_linear(*value);
+                                }
+                            }
+
+                            let alpha = if has_alpha { pixel[3] } else { 1.0 };
+                            for c in 0..channels {
+                                sum[c] += pixel[c];
+                                weighted_sum[c] += pixel[c] * alpha;
+                            }
+                            alpha_sum += alpha;
+                            count += 1.0;
+                        }
                     }
 
-                    Self::make_into_tiles(
-                        &mut self.tile_data[used_space..(used_space + img.data.len())],
-                        &img.data,
-                        self.tile_size,
-                        img.texture_descriptor.format,
-                    );
-
-                    used_space += img.data.len();
-                    if let Some(format) = format {
-                        assert_eq!(format, img.texture_descriptor.format);
-                    } else {
-                        format = Some(img.texture_descriptor.format);
+                    let dest = ((y * next_size.x + x) as usize) * pixel_size;
+                    for c in 0..channels {
+                        let mut value = if has_alpha && c < 3 && alpha_sum > 0.0 {
+                            weighted_sum[c] / alpha_sum
+                        } else {
+                            sum[c] / count
+                        };
+                        if srgb && c < 3 {
+                            value = convert::linear_to_srgb(value);
+                        }
+
+                        let offset = dest + c * channel.size();
+                        channel.write(
+                            value * channel.scale(),
+                            &mut next[offset..offset + channel.size()],
+                        );
                     }
                 }
             }
 
-            self.format = format;
-            if let Some(format) = self.format {
-                self.array_count = (used_space
-                    / (self.tile_size.x as usize * self.tile_size.y as usize * format.pixel_size()))
-                    as u32;
-            }
+            out.extend_from_slice(&next);
+            level = next;
+            level_size = next_size;
         }
     }
 
-    fn make_into_tiles(dest: &mut [u8], src: &[u8], tile_size: UVec2, format: TextureFormat) {
-        let pixel_size = format.pixel_size();
-
-        let tile_stride = tile_size.x as usize * pixel_size;
-        let row_stride = tile_size.y as usize * tile_stride;
-
-        for (idx, dest_chunk) in dest.chunks_exact_mut(tile_stride).enumerate() {
-            let x = (idx / tile_size.y as usize) % tile_size.x as usize;
-            let sub_tile_y = (tile_size.y - 1) as usize - (idx % tile_size.y as usize);
-            let y = idx / (tile_size.y * tile_size.x) as usize;
-
-            let src_start = (y * tile_size.y as usize * row_stride)
-                + (row_stride * sub_tile_y)
-                + (x * tile_stride);
-            let src_end = src_start + tile_stride;
+    Some((out, mip_level_count))
+}
 
-            dest_chunk.copy_from_slice(&src[src_start..src_end]);
+impl TileAnimationMode {
+    fn as_gpu(&self) -> u32 {
+        match self {
+            TileAnimationMode::Loop => 0,
+            TileAnimationMode::PingPong => 1,
+            TileAnimationMode::Once => 2,
         }
     }
 }
 
+#[derive(Clone, Copy, AsStd430)]
+struct AnimationUniform {
+    first_frame: u32,
+    frame_count: u32,
+    mode: u32,
+    duration: f32,
+}
+
+#[derive(Clone, Copy, AsStd430)]
+struct AnimationFrameUniform {
+    idx: u32,
+    // Time since the start of the animation at which this frame ends.
+    end: f32,
+}
+
 #[derive(Debug, Clone)]
 pub struct GpuTileSheet {
     pub bind_group: BindGroup,
@@ -143,22 +240,62 @@ impl RenderAsset for TileSheet {
             return Err(PrepareAssetError::RetryNextUpdate(tile_sheet));
         };
 
+        // Compressed formats need features that not every GPU has, like BC on desktop and ASTC
+        // or ETC2 on mobile.
+        let placeholder;
+        let (format, tile_size, array_count, tile_data) = if render_device
+            .features()
+            .contains(format.describe().required_features)
+        {
+            (
+                format,
+                tile_sheet.tile_size,
+                tile_sheet.array_count,
+                tile_sheet.tile_data.as_slice(),
+            )
+        } else {
+            error!(
+                "The GPU doesn't support the tile sheet format {:?}, its sprites are left blank",
+                format
+            );
+            // A transparent pixel for every sprite, so every sprite index stays a valid layer.
+            let array_count = tile_sheet.array_count.max(1);
+            placeholder = vec![0; 4 * array_count as usize];
+            (
+                TextureFormat::Rgba8UnormSrgb,
+                UVec2::ONE,
+                array_count,
+                placeholder.as_slice(),
+            )
+        };
+
+        let sampling = tile_sheet.sampling;
+        let mipmaps = if sampling.mipmaps {
+            generate_mipmaps(tile_data, tile_size, array_count, format)
+        } else {
+            None
+        };
+        let (data, mip_level_count) = match &mipmaps {
+            Some((data, mip_level_count)) => (data.as_slice(), *mip_level_count),
+            None => (tile_data, 1),
+        };
+
         let texture = render_device.create_texture_with_data(
             render_queue,
             &TextureDescriptor {
                 label: Some("TileSheet::Texture"),
                 size: Extent3d {
-                    width: tile_sheet.tile_size.x,
-                    height: tile_sheet.tile_size.y,
-                    depth_or_array_layers: tile_sheet.array_count,
+                    width: tile_size.x,
+                    height: tile_size.y,
+                    depth_or_array_layers: array_count,
                 },
-                mip_level_count: 1,
+                mip_level_count,
                 sample_count: 1,
                 dimension: TextureDimension::D2,
                 format: format,
                 usage: TextureUsages::COPY_DST | TextureUsages::TEXTURE_BINDING,
             },
-            &tile_sheet.tile_data,
+            data,
         );
 
         let sampler = render_device.create_sampler(&SamplerDescriptor {
@@ -166,13 +303,13 @@ impl RenderAsset for TileSheet {
             address_mode_u: AddressMode::ClampToEdge,
             address_mode_v: AddressMode::ClampToEdge,
             address_mode_w: AddressMode::ClampToEdge,
-            mag_filter: FilterMode::Nearest,
-            min_filter: FilterMode::Nearest,
-            mipmap_filter: FilterMode::Nearest,
+            mag_filter: sampling.filter.as_gpu(),
+            min_filter: sampling.filter.as_gpu(),
+            mipmap_filter: sampling.filter.as_gpu(),
             lod_min_clamp: 0.0,
             lod_max_clamp: std::f32::MAX,
             compare: None,
-            anisotropy_clamp: None,
+            anisotropy_clamp: sampling.anisotropy_clamp(),
             border_color: None,
         });
 
@@ -184,9 +321,31 @@ impl RenderAsset for TileSheet {
             base_mip_level: 0,
             mip_level_count: None,
             base_array_layer: 0,
-            array_layer_count: NonZeroU32::new(tile_sheet.array_count),
+            array_layer_count: NonZeroU32::new(array_count),
         });
 
+        let mut animations = StorageBuffer::<AnimationUniform>::default();
+        let mut frames = StorageBuffer::<AnimationFrameUniform>::default();
+        for animation in &tile_sheet.animations {
+            animations.push(AnimationUniform {
+                first_frame: frames.values().len() as u32,
+                frame_count: animation.frames.len() as u32,
+                mode: animation.mode.as_gpu(),
+                duration: animation.duration(),
+            });
+
+            let mut end = 0.0;
+            for frame in &animation.frames {
+                end += frame.duration;
+                frames.push(AnimationFrameUniform {
+                    idx: frame.idx as u32,
+                    end,
+                });
+            }
+        }
+        animations.write_buffer(render_device, render_queue);
+        frames.write_buffer(render_device, render_queue);
+
         let bind_group = render_device.create_bind_group(&BindGroupDescriptor {
             entries: &[
                 BindGroupEntry {
@@ -197,6 +356,14 @@ impl RenderAsset for TileSheet {
                     binding: 1,
                     resource: BindingResource::Sampler(&sampler),
                 },
+                BindGroupEntry {
+                    binding: 2,
+                    resource: animations.binding().unwrap(),
+                },
+                BindGroupEntry {
+                    binding: 3,
+                    resource: frames.binding().unwrap(),
+                },
             ],
             label: Some("TileMap::TileSheet::BindGroup"),
             layout: &tile_map_pipeline.texture_sampler_layout,
@@ -205,3 +372,58 @@ impl RenderAsset for TileSheet {
         Ok(GpuTileSheet { bind_group })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mipmaps_of_every_layer() {
+        let data: Vec<u8> = [[10, 20, 30, 40], [50, 60, 70, 80]].concat();
+        let (mipmaps, mip_level_count) =
+            generate_mipmaps(&data, UVec2::new(2, 2), 2, TextureFormat::R8Unorm).unwrap();
+
+        assert_eq!(mip_level_count, 2);
+        assert_eq!(mipmaps, vec![10, 20, 30, 40, 25, 50, 60, 70, 80, 65]);
+    }
+
+    #[test]
+    fn srgb_mipmaps_are_filtered_in_linear_space() {
+        let data = [[0, 0, 0, 255], [255, 255, 255, 255]].concat();
+        let (mipmaps, _) =
+            generate_mipmaps(&data, UVec2::new(2, 1), 1, TextureFormat::Rgba8UnormSrgb).unwrap();
+
+        // Half way between black and white in linear space.
+        assert_eq!(&mipmaps[8..], &[188, 188, 188, 255]);
+    }
+
+    #[test]
+    fn transparent_pixels_dont_bleed_in_to_mipmaps() {
+        let data = [[255, 0, 0, 255], [0, 255, 0, 0]].concat();
+        let (mipmaps, _) =
+            generate_mipmaps(&data, UVec2::new(2, 1), 1, TextureFormat::Rgba8Unorm).unwrap();
+
+        assert_eq!(&mipmaps[8..], &[255, 0, 0, 128]);
+    }
+
+    #[test]
+    fn no_mipmaps_for_compressed_formats() {
+        assert!(
+            generate_mipmaps(&[0; 16], UVec2::new(4, 4), 1, TextureFormat::Bc1RgbaUnorm).is_none()
+        );
+    }
+
+    #[test]
+    fn anisotropy_is_a_power_of_two() {
+        let sampling = |anisotropy| TileSheetSampling {
+            anisotropy,
+            ..TileSheetSampling::SMOOTH
+        };
+
+        assert_eq!(sampling(1).anisotropy_clamp(), None);
+        assert_eq!(sampling(3).anisotropy_clamp(), NonZeroU8::new(2));
+        assert_eq!(sampling(12).anisotropy_clamp(), NonZeroU8::new(8));
+        assert_eq!(sampling(255).anisotropy_clamp(), NonZeroU8::new(16));
+        assert_eq!(TileSheetSampling::PIXEL_ART.anisotropy_clamp(), None);
+    }
+}
@@ -0,0 +1,332 @@
+use bevy::{prelude::*, utils::HashSet};
+
+use crate::{Tile, TileMap};
+
+/// A set of tile coordinates, for example the tiles picked by a `TileSelector`.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct TileSelection {
+    tiles: HashSet<UVec3>,
+}
+
+impl TileSelection {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Every tile in the box between `a` and `b`, both included.
+    pub fn from_box(a: UVec3, b: UVec3) -> Self {
+        let min = a.min(b);
+        let max = a.max(b);
+
+        let mut selection = Self::new();
+        for z in min.z..=max.z {
+            for y in min.y..=max.y {
+                for x in min.x..=max.x {
+                    selection.insert(UVec3::new(x, y, z));
+                }
+            }
+        }
+        selection
+    }
+
+    /// Every tile on layer `layer` whose center is inside of the polygon through `points`, and
+    /// the tiles of the points themselves.
+    pub fn from_lasso(points: &[UVec2], layer: u32) -> Self {
+        let mut selection = Self::new();
+        let (min, max) = match points
+            .iter()
+            .copied()
+            .reduce(UVec2::min)
+            .zip(points.iter().copied().reduce(UVec2::max))
+        {
+            Some(bounds) => bounds,
+            None => return selection,
+        };
+
+        let corners: Vec<Vec2> = points.iter().map(|point| point.as_vec2()).collect();
+        for y in min.y..=max.y {
+            for x in min.x..=max.x {
+                let center = UVec2::new(x, y).as_vec2();
+
+                // Even-odd rule, counting the polygon edges crossed by a ray going right.
+                let mut inside = false;
+                for (start, end) in corners.iter().zip(corners.iter().cycle().skip(1)) {
+                    if (start.y > center.y) != (end.y > center.y) {
+                        let t = (center.y - start.y) / (end.y - start.y);
+                        if center.x < start.x + t * (end.x - start.x) {
+                            inside = !inside;
+                        }
+                    }
+                }
+
+                if inside {
+                    selection.insert(UVec3::new(x, y, layer));
+                }
+            }
+        }
+
+        selection.extend(points.iter().map(|point| point.extend(layer)));
+        selection
+    }
+
+    #[inline]
+    pub fn insert(&mut self, coord: UVec3) -> bool {
+        self.tiles.insert(coord)
+    }
+
+    #[inline]
+    pub fn remove(&mut self, coord: UVec3) -> bool {
+        self.tiles.remove(&coord)
+    }
+
+    #[inline]
+    pub fn contains(&self, coord: UVec3) -> bool {
+        self.tiles.contains(&coord)
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.tiles.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.tiles.is_empty()
+    }
+
+    #[inline]
+    pub fn clear(&mut self) {
+        self.tiles.clear();
+    }
+
+    #[inline]
+    pub fn iter(&self) -> impl Iterator<Item = UVec3> + '_ {
+        self.tiles.iter().copied()
+    }
+
+    pub fn union(&self, other: &TileSelection) -> TileSelection {
+        TileSelection {
+            tiles: self.tiles.union(&other.tiles).copied().collect(),
+        }
+    }
+
+    pub fn intersection(&self, other: &TileSelection) -> TileSelection {
+        TileSelection {
+            tiles: self.tiles.intersection(&other.tiles).copied().collect(),
+        }
+    }
+
+    pub fn difference(&self, other: &TileSelection) -> TileSelection {
+        TileSelection {
+            tiles: self.tiles.difference(&other.tiles).copied().collect(),
+        }
+    }
+
+    /// Every tile of a map of `size` that isn't selected.
+    pub fn invert(&self, size: UVec3) -> TileSelection {
+        if size.cmpeq(UVec3::ZERO).any() {
+            return TileSelection::new();
+        }
+
+        TileSelection::from_box(UVec3::ZERO, size - UVec3::ONE).difference(self)
+    }
+
+    /// The smallest and largest coordinate of the selection, both included.
+    pub fn bounding_box(&self) -> Option<(UVec3, UVec3)> {
+        let min = self.iter().reduce(UVec3::min)?;
+        let max = self.iter().reduce(UVec3::max)?;
+        Some((min, max))
+    }
+
+    /// Sets every selected tile of `tile_map` to `tile`. Tiles outside of the map are skipped.
+    pub fn fill(&self, tile_map: &mut TileMap, tile: Tile) {
+        for coord in self.iter() {
+            if coord.cmplt(tile_map.size).all() {
+                tile_map[coord] = tile;
+            }
+        }
+    }
+
+    /// Empties every selected tile of `tile_map`.
+    pub fn clear_tiles(&self, tile_map: &mut TileMap) {
+        self.fill(tile_map, Tile::default());
+    }
+
+    /// The selected tiles of `tile_map`, with coordinates relative to the minimum of the bounding
+    /// box. See `paste`.
+    pub fn copy(&self, tile_map: &TileMap) -> Vec<(UVec3, Tile)> {
+        let min = if let Some((min, _)) = self.bounding_box() {
+            min
+        } else {
+            return Vec::new();
+        };
+
+        self.iter()
+            .filter(|coord| coord.cmplt(tile_map.size).all())
+            .map(|coord| (coord - min, tile_map[coord]))
+            .collect()
+    }
+
+    /// Writes tiles returned by `copy` to `tile_map`, with `offset` added to their coordinates.
+    pub fn paste(tile_map: &mut TileMap, tiles: &[(UVec3, Tile)], offset: UVec3) {
+        for (coord, tile) in tiles {
+            let coord = *coord + offset;
+            if coord.cmplt(tile_map.size).all() {
+                tile_map[coord] = *tile;
+            }
+        }
+    }
+}
+
+impl Extend<UVec3> for TileSelection {
+    fn extend<T: IntoIterator<Item = UVec3>>(&mut self, iter: T) {
+        self.tiles.extend(iter);
+    }
+}
+
+impl FromIterator<UVec3> for TileSelection {
+    fn from_iter<T: IntoIterator<Item = UVec3>>(iter: T) -> Self {
+        TileSelection {
+            tiles: iter.into_iter().collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{TileKind, TileTransform};
+
+    fn selection(coords: &[[u32; 3]]) -> TileSelection {
+        coords.iter().copied().map(UVec3::from).collect()
+    }
+
+    fn tile(color: Color) -> Tile {
+        Tile {
+            entity: None,
+            kind: Some(TileKind::Color {
+                color,
+                transform: TileTransform::default(),
+            }),
+            pickable: false,
+        }
+    }
+
+    #[test]
+    fn box_includes_both_corners_in_any_order() {
+        let expected = selection(&[[1, 2, 0], [2, 2, 0], [1, 3, 0], [2, 3, 0]]);
+        assert_eq!(
+            TileSelection::from_box(UVec3::new(1, 2, 0), UVec3::new(2, 3, 0)),
+            expected
+        );
+        assert_eq!(
+            TileSelection::from_box(UVec3::new(2, 2, 0), UVec3::new(1, 3, 0)),
+            expected
+        );
+        assert_eq!(
+            TileSelection::from_box(UVec3::new(1, 1, 1), UVec3::new(1, 1, 1)),
+            selection(&[[1, 1, 1]])
+        );
+    }
+
+    #[test]
+    fn lasso_selects_the_inside_of_concave_polygons() {
+        let points: Vec<UVec2> = [[0, 0], [6, 0], [6, 2], [2, 2], [2, 6], [0, 6]]
+            .into_iter()
+            .map(UVec2::from)
+            .collect();
+        let lasso = TileSelection::from_lasso(&points, 1);
+
+        assert!(lasso.contains(UVec3::new(1, 4, 1)));
+        assert!(lasso.contains(UVec3::new(4, 1, 1)));
+        assert!(!lasso.contains(UVec3::new(4, 4, 1)));
+        assert!(!lasso.contains(UVec3::new(1, 4, 0)));
+        for point in points {
+            assert!(lasso.contains(point.extend(1)));
+        }
+        assert!(lasso.iter().all(|coord| coord.x <= 6 && coord.y <= 6));
+    }
+
+    #[test]
+    fn lasso_without_points_is_empty() {
+        assert!(TileSelection::from_lasso(&[], 0).is_empty());
+        assert_eq!(
+            TileSelection::from_lasso(&[UVec2::new(3, 4)], 2),
+            selection(&[[3, 4, 2]])
+        );
+    }
+
+    #[test]
+    fn set_operations() {
+        let a = selection(&[[0, 0, 0], [1, 0, 0]]);
+        let b = selection(&[[1, 0, 0], [2, 0, 0]]);
+
+        assert_eq!(a.union(&b), selection(&[[0, 0, 0], [1, 0, 0], [2, 0, 0]]));
+        assert_eq!(a.intersection(&b), selection(&[[1, 0, 0]]));
+        assert_eq!(a.difference(&b), selection(&[[0, 0, 0]]));
+        assert_eq!(b.difference(&a), selection(&[[2, 0, 0]]));
+    }
+
+    #[test]
+    fn invert_selects_the_rest_of_the_map() {
+        let selected = selection(&[[0, 0, 0], [1, 1, 1]]);
+        let inverted = selected.invert(UVec3::new(2, 2, 2));
+
+        assert_eq!(inverted.len(), 6);
+        assert!(inverted.intersection(&selected).is_empty());
+        assert_eq!(inverted.union(&selected).len(), 8);
+        assert!(selected.invert(UVec3::new(2, 0, 2)).is_empty());
+    }
+
+    #[test]
+    fn bounding_box() {
+        assert_eq!(TileSelection::new().bounding_box(), None);
+        assert_eq!(
+            selection(&[[3, 1, 0], [1, 4, 2]]).bounding_box(),
+            Some((UVec3::new(1, 1, 0), UVec3::new(3, 4, 2)))
+        );
+    }
+
+    #[test]
+    fn copy_and_paste_move_tiles_relative_to_the_bounding_box() {
+        let mut tile_map = TileMap::new(
+            UVec2::new(4, 4),
+            UVec2::new(4, 4),
+            UVec2::ONE,
+            Handle::default(),
+        );
+        tile_map[UVec3::new(1, 1, 0)] = tile(Color::RED);
+        tile_map[UVec3::new(2, 1, 0)] = tile(Color::BLUE);
+
+        let copied = selection(&[[1, 1, 0], [2, 1, 0]]).copy(&tile_map);
+        assert_eq!(copied.len(), 2);
+        assert!(copied.contains(&(UVec3::new(0, 0, 0), tile(Color::RED))));
+        assert!(copied.contains(&(UVec3::new(1, 0, 0), tile(Color::BLUE))));
+
+        TileSelection::paste(&mut tile_map, &copied, UVec3::new(2, 2, 0));
+        assert_eq!(tile_map[UVec3::new(2, 2, 0)], tile(Color::RED));
+        assert_eq!(tile_map[UVec3::new(3, 2, 0)], tile(Color::BLUE));
+
+        // The tile that would land outside of the map is skipped.
+        TileSelection::paste(&mut tile_map, &copied, UVec3::new(3, 0, 0));
+        assert_eq!(tile_map[UVec3::new(3, 0, 0)], tile(Color::RED));
+    }
+
+    #[test]
+    fn fill_and_clear_skip_tiles_outside_of_the_map() {
+        let mut tile_map = TileMap::new(
+            UVec2::new(2, 2),
+            UVec2::new(2, 2),
+            UVec2::ONE,
+            Handle::default(),
+        );
+        let selected = selection(&[[0, 0, 0], [5, 0, 0], [0, 0, 3]]);
+
+        selected.fill(&mut tile_map, tile(Color::RED));
+        assert_eq!(tile_map[UVec3::new(0, 0, 0)], tile(Color::RED));
+        assert_eq!(tile_map[UVec3::new(1, 0, 0)], Tile::default());
+
+        selected.clear_tiles(&mut tile_map);
+        assert_eq!(tile_map[UVec3::new(0, 0, 0)], Tile::default());
+    }
+}
@@ -0,0 +1,213 @@
+use bevy::{prelude::*, render::camera::Camera2d, utils::HashSet};
+
+use crate::{
+    chunk::{ChunkCoord, ChunkEntities},
+    TileMap,
+};
+
+/// Add this next to a `TileMap` to only keep the chunks near a camera spawned. A chunk gets
+/// spawned once it is closer than `load_radius` to any 2d camera and despawned, together with its
+/// render resources, once it is further than `unload_radius` from every 2d camera. The tiles stay
+/// in the `TileMap` either way.
+///
+/// Distances are in the local space of the tile map and measured to the closest point of a chunk.
+#[derive(Debug, Clone, Component)]
+pub struct ChunkStreaming {
+    pub load_radius: f32,
+    pub unload_radius: f32,
+    // The x and y of the loaded chunks, a loaded chunk is loaded on every layer.
+    pub(crate) loaded: HashSet<UVec2>,
+}
+
+impl ChunkStreaming {
+    pub fn new(load_radius: f32, unload_radius: f32) -> Self {
+        assert!(
+            unload_radius >= load_radius,
+            "The unload radius has to be at least as big as the load radius"
+        );
+
+        ChunkStreaming {
+            load_radius,
+            unload_radius,
+            loaded: HashSet::default(),
+        }
+    }
+
+    #[inline]
+    pub fn is_loaded(&self, coord: ChunkCoord) -> bool {
+        self.loaded.contains(&coord.truncate())
+    }
+
+    /// The loaded chunks that are further than `unload_radius` from every center and the chunks
+    /// that aren't loaded yet but are closer than `load_radius` to any center. `centers` are in
+    /// the local space of the tile map.
+    fn changes(
+        &self,
+        centers: &[Vec2],
+        chunk_pixels: Vec2,
+        chunk_count: UVec2,
+    ) -> (Vec<UVec2>, Vec<UVec2>) {
+        let distance = |center: Vec2, chunk: UVec2| {
+            let min = chunk.as_vec2() * chunk_pixels;
+            (center.clamp(min, min + chunk_pixels) - center).length()
+        };
+
+        let unloaded = self
+            .loaded
+            .iter()
+            .copied()
+            .filter(|chunk| {
+                centers
+                    .iter()
+                    .all(|center| distance(*center, *chunk) > self.unload_radius)
+            })
+            .collect();
+
+        let mut loaded = HashSet::default();
+        if chunk_count.x == 0 || chunk_count.y == 0 {
+            return (unloaded, Vec::new());
+        }
+
+        for center in centers.iter() {
+            let min = ((*center - self.load_radius) / chunk_pixels)
+                .floor()
+                .max(Vec2::ZERO)
+                .as_uvec2();
+            let max = ((*center + self.load_radius) / chunk_pixels)
+                .floor()
+                .max(Vec2::ZERO)
+                .as_uvec2()
+                .min(chunk_count - UVec2::ONE);
+
+            for x in min.x..=max.x {
+                for y in min.y..=max.y {
+                    let chunk = UVec2::new(x, y);
+                    if distance(*center, chunk) <= self.load_radius && !self.loaded.contains(&chunk)
+                    {
+                        loaded.insert(chunk);
+                    }
+                }
+            }
+        }
+
+        (unloaded, loaded.into_iter().collect())
+    }
+}
+
+pub(crate) fn stream_chunks(
+    mut commands: Commands,
+    // Chunks are only drawn by 2d cameras, the UI camera would keep the chunks around the origin
+    // loaded.
+    cameras: Query<&GlobalTransform, With<Camera2d>>,
+    mut tile_maps: Query<(
+        &mut TileMap,
+        &mut ChunkEntities,
+        &mut ChunkStreaming,
+        &GlobalTransform,
+    )>,
+) {
+    for (mut tile_map, mut chunk_entities, mut streaming, transform) in tile_maps.iter_mut() {
+        let to_local = transform.compute_matrix().inverse();
+        let centers: Vec<Vec2> = cameras
+            .iter()
+            .map(|camera| to_local.transform_point3(camera.translation).truncate())
+            .collect();
+
+        let chunk_pixels = (tile_map.chunk_size * tile_map.tile_size).as_vec2();
+        let chunk_count =
+            (tile_map.size.truncate() + tile_map.chunk_size - UVec2::ONE) / tile_map.chunk_size;
+
+        // Only touch the components if something changes, so their change detection stays quiet.
+        let (unloaded, loaded) = streaming.changes(&centers, chunk_pixels, chunk_count);
+        for chunk in unloaded {
+            streaming.loaded.remove(&chunk);
+        }
+        for chunk in loaded {
+            streaming.loaded.insert(chunk);
+            for z in 0..tile_map.size.z {
+                tile_map.dirty_chunks.insert(ChunkCoord(chunk.extend(z)));
+            }
+        }
+
+        // Also catches chunks that were spawned before the map started streaming.
+        let despawn = chunk_entities
+            .keys()
+            .any(|coord| !streaming.is_loaded(*coord));
+        if despawn {
+            chunk_entities.retain(|coord, entity| {
+                let keep = streaming.is_loaded(*coord);
+                if !keep {
+                    commands.entity(*entity).despawn_recursive();
+                }
+                keep
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CHUNK_PIXELS: Vec2 = bevy::math::const_vec2!([10.0, 10.0]);
+    const CHUNK_COUNT: UVec2 = bevy::math::const_uvec2!([10, 10]);
+
+    fn sorted(mut chunks: Vec<UVec2>) -> Vec<UVec2> {
+        chunks.sort_by_key(|chunk| (chunk.x, chunk.y));
+        chunks
+    }
+
+    #[test]
+    fn loads_the_chunks_within_the_load_radius() {
+        let streaming = ChunkStreaming::new(4.0, 8.0);
+        let (unloaded, loaded) =
+            streaming.changes(&[Vec2::new(15.0, 15.0)], CHUNK_PIXELS, CHUNK_COUNT);
+
+        assert!(unloaded.is_empty());
+        assert_eq!(loaded, vec![UVec2::new(1, 1)]);
+
+        // Exactly on the border between two chunks.
+        let (_, loaded) = streaming.changes(&[Vec2::new(20.0, 15.0)], CHUNK_PIXELS, CHUNK_COUNT);
+        assert_eq!(sorted(loaded), vec![UVec2::new(1, 1), UVec2::new(2, 1)]);
+    }
+
+    #[test]
+    fn chunks_between_the_radii_stay_loaded() {
+        let mut streaming = ChunkStreaming::new(4.0, 8.0);
+        streaming.loaded.insert(UVec2::new(1, 1));
+
+        // 5 away from the chunk, too far to load it but close enough to keep it.
+        let (unloaded, loaded) =
+            streaming.changes(&[Vec2::new(25.0, 15.0)], CHUNK_PIXELS, CHUNK_COUNT);
+        assert!(unloaded.is_empty());
+        assert_eq!(loaded, vec![UVec2::new(2, 1)]);
+
+        // 9 away, further than the unload radius.
+        let (unloaded, _) = streaming.changes(&[Vec2::new(29.0, 15.0)], CHUNK_PIXELS, CHUNK_COUNT);
+        assert_eq!(unloaded, vec![UVec2::new(1, 1)]);
+    }
+
+    #[test]
+    fn any_center_keeps_a_chunk_loaded() {
+        let mut streaming = ChunkStreaming::new(4.0, 8.0);
+        streaming.loaded.insert(UVec2::new(1, 1));
+
+        let centers = [Vec2::new(95.0, 95.0), Vec2::new(15.0, 15.0)];
+        let (unloaded, loaded) = streaming.changes(&centers, CHUNK_PIXELS, CHUNK_COUNT);
+        assert!(unloaded.is_empty());
+        assert_eq!(loaded, vec![UVec2::new(9, 9)]);
+
+        let (unloaded, _) = streaming.changes(&[], CHUNK_PIXELS, CHUNK_COUNT);
+        assert_eq!(unloaded, vec![UVec2::new(1, 1)]);
+    }
+
+    #[test]
+    fn only_loads_chunks_inside_of_the_map() {
+        let streaming = ChunkStreaming::new(4.0, 8.0);
+        let (_, loaded) = streaming.changes(&[Vec2::new(-5.0, 105.0)], CHUNK_PIXELS, CHUNK_COUNT);
+        assert!(loaded.is_empty());
+
+        let (_, loaded) = streaming.changes(&[Vec2::new(-2.0, 98.0)], CHUNK_PIXELS, CHUNK_COUNT);
+        assert_eq!(loaded, vec![UVec2::new(0, 9)]);
+    }
+}
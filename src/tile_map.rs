@@ -2,12 +2,13 @@ use std::ops::{Index, IndexMut};
 
 use bevy::{
     prelude::*,
-    utils::{HashMap, HashSet},
+    utils::{HashMap, HashSet, Instant},
 };
 
 use crate::{
     chunk::{ChunkCoord, ChunkEntities},
-    TileSheet,
+    diagnostics::CoreStats,
+    TileHighlights, TileSheet,
 };
 
 #[derive(Debug, Default, Component)]
@@ -18,6 +19,7 @@ pub struct TileMap {
     pub tile_size: UVec2,
     pub(crate) dirty_chunks: HashSet<ChunkCoord>,
     pub(crate) tile_sheet: Handle<TileSheet>,
+    pub(crate) unpickable_layers: HashSet<u32>,
 }
 
 impl TileMap {
@@ -36,6 +38,7 @@ impl TileMap {
             tile_size,
             dirty_chunks: HashSet::default(),
             tile_sheet,
+            unpickable_layers: HashSet::default(),
         }
     }
 
@@ -82,6 +85,21 @@ impl TileMap {
         self.tiles.len() as u32 - 1
     }
 
+    /// Tiles on an unpickable layer are skipped by tile picking, even if they are `pickable`.
+    /// Every layer starts out pickable.
+    pub fn set_layer_pickable(&mut self, layer: u32, pickable: bool) {
+        if pickable {
+            self.unpickable_layers.remove(&layer);
+        } else {
+            self.unpickable_layers.insert(layer);
+        }
+    }
+
+    #[inline]
+    pub fn is_layer_pickable(&self, layer: u32) -> bool {
+        !self.unpickable_layers.contains(&layer)
+    }
+
     #[inline]
     pub fn size(&self) -> UVec3 {
         self.size
@@ -89,7 +107,9 @@ impl TileMap {
 
     #[inline]
     pub fn chunks(&self) -> impl IntoIterator<Item = ChunkCoord> {
-        let max = self.coord_to_chunk_coord(self.size).0;
+        // Round up, the last chunk of a row or column may only be partially inside of the map.
+        let max = ((self.size.truncate() + self.chunk_size - UVec2::ONE) / self.chunk_size)
+            .extend(self.size.z);
 
         (0..max.x)
             .flat_map(move |x| (0..max.y).map(move |y| UVec2::new(x, y)))
@@ -107,6 +127,43 @@ impl TileMap {
         ChunkCoord((coord.truncate() / self.chunk_size).extend(coord.z))
     }
 
+    /// The index in the chunk and the coord in the map of every tile of the chunk that is inside
+    /// of the map.
+    pub(crate) fn chunk_tile_coords(
+        &self,
+        chunk_coord: ChunkCoord,
+    ) -> impl Iterator<Item = (usize, UVec3)> {
+        let chunk_size = self.chunk_size;
+        let start = chunk_coord.truncate() * chunk_size;
+        let end = (start + chunk_size).min(self.size.truncate());
+
+        (start.y..end.y).flat_map(move |y| {
+            (start.x..end.x).map(move |x| {
+                let index = ((y - start.y) * chunk_size.x + x - start.x) as usize;
+                (index, UVec3::new(x, y, chunk_coord.z))
+            })
+        })
+    }
+
+    /// The tiles of a chunk, row by row. Tiles outside of the map are empty.
+    pub(crate) fn chunk_tiles(&self, chunk_coord: ChunkCoord) -> Vec<Tile> {
+        let mut tiles = vec![Tile::default(); (self.chunk_size.x * self.chunk_size.y) as usize];
+        for (index, coord) in self.chunk_tile_coords(chunk_coord) {
+            tiles[index] = self[coord];
+        }
+        tiles
+    }
+
+    /// Overwrites the tiles of a chunk with `tiles`, row by row.
+    pub(crate) fn set_chunk_tiles(&mut self, chunk_coord: ChunkCoord, tiles: &[Tile]) {
+        for (index, coord) in self.chunk_tile_coords(chunk_coord).collect::<Vec<_>>() {
+            if let Some(tile) = tiles.get(index) {
+                self[coord] = *tile;
+            }
+        }
+        self.dirty_chunks.insert(chunk_coord);
+    }
+
     #[inline]
     pub fn mark_chunk_dirty(&mut self, coord: UVec3) {
         self.dirty_chunks.insert(self.coord_to_chunk_coord(coord));
@@ -188,6 +245,56 @@ pub enum TileKind {
         transform: TileTransform,
         mask_color: Color,
     },
+    /// A sprite that cycles through the frames of `TileSheet` animation `animation`. The frame is
+    /// picked on the GPU, so animated tiles don't cause any chunk updates.
+    AnimatedSprite {
+        animation: u16,
+        transform: TileTransform,
+        mask_color: Color,
+    },
+}
+
+bitflags::bitflags! {
+    /// Exact flips of a sprite, with the same meaning as the flip bits of Tiled and LDtk. They
+    /// are applied anti-diagonal first, then x and then y, before the rest of the `TileTransform`.
+    #[derive(Default)]
+    pub struct TileFlip: u8 {
+        const X                 = 0b001;
+        const Y                 = 0b010;
+        /// Mirrors the sprite along the line from its top left to its bottom right corner.
+        const ANTI_DIAGONAL     = 0b100;
+
+        const ROTATE_90         = Self::ANTI_DIAGONAL.bits | Self::X.bits;
+        const ROTATE_180        = Self::X.bits | Self::Y.bits;
+        const ROTATE_270        = Self::ANTI_DIAGONAL.bits | Self::Y.bits;
+    }
+}
+
+impl TileFlip {
+    /// A clockwise rotation by `quarter_turns` * 90°.
+    pub fn rotation(quarter_turns: u32) -> Self {
+        match quarter_turns % 4 {
+            0 => TileFlip::empty(),
+            1 => TileFlip::ROTATE_90,
+            2 => TileFlip::ROTATE_180,
+            _ => TileFlip::ROTATE_270,
+        }
+    }
+
+    /// Maps a position on the tile, with (0, 0) at the bottom left corner, to the position on the
+    /// sprite that is shown there. Matches what `chunk.wgsl` does.
+    pub fn apply(&self, mut uv: Vec2) -> Vec2 {
+        if self.contains(TileFlip::Y) {
+            uv.y = 1.0 - uv.y;
+        }
+        if self.contains(TileFlip::X) {
+            uv.x = 1.0 - uv.x;
+        }
+        if self.contains(TileFlip::ANTI_DIAGONAL) {
+            uv = Vec2::new(1.0 - uv.y, 1.0 - uv.x);
+        }
+        uv
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -195,9 +302,17 @@ pub struct TileTransform {
     pub angle: f32,
     pub translation: Vec2,
     pub scale: Vec2,
+    pub flip: TileFlip,
 }
 
 impl TileTransform {
+    pub fn from_flip(flip: TileFlip) -> Self {
+        TileTransform {
+            flip,
+            ..Default::default()
+        }
+    }
+
     pub fn recenter(&self) -> Self {
         let current: Mat3 = self.clone().into();
         let offset = current.transform_point2(Vec2::new(0.5, 0.5));
@@ -206,6 +321,7 @@ impl TileTransform {
             angle: self.angle,
             translation: self.translation - (Vec2::new(0.5, 0.5) - offset),
             scale: self.scale,
+            flip: self.flip,
         }
     }
 }
@@ -216,6 +332,7 @@ impl Default for TileTransform {
             translation: Vec2::ZERO,
             angle: 0.0,
             scale: Vec2::ONE,
+            flip: TileFlip::empty(),
         }
     }
 }
@@ -248,6 +365,7 @@ impl Into<Mat4> for &TileTransform {
 pub struct TileMapBundle {
     pub tile_map: TileMap,
     pub chunks: ChunkEntities,
+    pub highlights: TileHighlights,
     #[bundle]
     pub transform: TransformBundle,
 }
@@ -263,7 +381,10 @@ pub(crate) fn sync_as_tiles(
     mut sync_cache: Local<HashMap<Entity, AsTiles>>,
     mut tile_maps: Query<&mut TileMap>,
     as_tiles_query: Query<(Entity, &AsTiles)>,
+    stats: Option<ResMut<CoreStats>>,
 ) {
+    let start = Instant::now();
+
     for (as_tiles_entity, as_tiles) in as_tiles_query.iter() {
         if let Ok(mut tile_map) = tile_maps.get_mut(as_tiles.tile_map_entity) {
             if let Some(old_tiles) = sync_cache.get_mut(&as_tiles_entity) {
@@ -290,4 +411,55 @@ pub(crate) fn sync_as_tiles(
             warn!("TileMap entity for a AsTiles does not exist");
         }
     }
+
+    if let Some(mut stats) = stats {
+        stats.sync_as_tiles_time = start.elapsed();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::math::const_vec2;
+
+    use super::*;
+
+    const CORNERS: [Vec2; 4] = [
+        const_vec2!([0.0, 0.0]),
+        const_vec2!([1.0, 0.0]),
+        const_vec2!([1.0, 1.0]),
+        const_vec2!([0.0, 1.0]),
+    ];
+
+    #[test]
+    fn flips_mirror_the_sprite() {
+        let uv = Vec2::new(0.25, 0.75);
+
+        assert_eq!(TileFlip::empty().apply(uv), uv);
+        assert_eq!(TileFlip::X.apply(uv), Vec2::new(0.75, 0.75));
+        assert_eq!(TileFlip::Y.apply(uv), Vec2::new(0.25, 0.25));
+        assert_eq!(TileFlip::ANTI_DIAGONAL.apply(uv), Vec2::new(0.25, 0.75));
+        assert_eq!(
+            TileFlip::ANTI_DIAGONAL.apply(Vec2::new(0.0, 0.0)),
+            Vec2::new(1.0, 1.0)
+        );
+    }
+
+    #[test]
+    fn rotating_a_quarter_turn_moves_the_bottom_left_corner_to_the_top_left() {
+        assert_eq!(TileFlip::rotation(1).apply(CORNERS[3]), CORNERS[0]);
+        assert_eq!(TileFlip::rotation(1).apply(CORNERS[2]), CORNERS[3]);
+    }
+
+    #[test]
+    fn rotations_compose() {
+        for uv in CORNERS.into_iter().chain([Vec2::new(0.2, 0.7)]) {
+            let quarter = TileFlip::rotation(1);
+            let mut rotated = uv;
+            for quarter_turns in 1..=4 {
+                rotated = quarter.apply(rotated);
+                assert!(rotated.abs_diff_eq(TileFlip::rotation(quarter_turns).apply(uv), 1e-6));
+            }
+            assert!(rotated.abs_diff_eq(uv, 1e-6));
+        }
+    }
 }
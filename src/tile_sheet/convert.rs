@@ -0,0 +1,288 @@
+use bevy::{
+    prelude::*,
+    render::{
+        render_resource::{TextureFormat, TextureSampleType},
+        texture::TextureFormatPixelInfo,
+    },
+};
+
+/// Whether images can be converted from `format`.
+pub(crate) fn can_decode(format: TextureFormat) -> bool {
+    decode(format, &[0; 16]).is_some()
+}
+
+/// Whether the chunk shader can sample textures in `format` without converting them. Single and
+/// two channel formats would be sampled as red and red-green, so they are always converted to
+/// the gray `decode` reads them as, no matter which images they are mixed with.
+pub(crate) fn can_sample(format: TextureFormat) -> bool {
+    let info = format.describe();
+    info.components >= 3
+        && matches!(
+            info.sample_type,
+            TextureSampleType::Float { filterable: true }
+        )
+}
+
+/// Whether images can be converted to `format`.
+pub(crate) fn can_encode(format: TextureFormat) -> bool {
+    matches!(
+        format,
+        TextureFormat::Rgba8Unorm | TextureFormat::Rgba8UnormSrgb | TextureFormat::Rgba16Float
+    )
+}
+
+/// Converts the pixels in `src` from `from` to `to`, appending them to `dest`. Both formats have
+/// to be supported, see `can_decode` and `can_encode`.
+pub(crate) fn convert(src: &[u8], from: TextureFormat, to: TextureFormat, dest: &mut Vec<u8>) {
+    for pixel in src.chunks_exact(from.pixel_size()) {
+        encode(to, decode(from, pixel).unwrap(), dest);
+    }
+}
+
+/// The alpha of a pixel, formats without alpha or that can't be decoded count as opaque.
+pub(crate) fn alpha(format: TextureFormat, pixel: &[u8]) -> f32 {
+    decode(format, pixel).map_or(1.0, |color| color.w)
+}
+
+/// The linear RGBA color of a pixel. Single and two channel formats are read as luminance and
+/// luminance with alpha, like the image loader of bevy creates them.
+fn decode(format: TextureFormat, pixel: &[u8]) -> Option<Vec4> {
+    let unorm8 = |i: usize| pixel[i] as f32 / 255.0;
+    let unorm16 = |i: usize| u16::from_le_bytes([pixel[i * 2], pixel[i * 2 + 1]]) as f32 / 65535.0;
+    let float16 = |i: usize| f16_to_f32(u16::from_le_bytes([pixel[i * 2], pixel[i * 2 + 1]]));
+    let float32 = |i: usize| {
+        f32::from_le_bytes([
+            pixel[i * 4],
+            pixel[i * 4 + 1],
+            pixel[i * 4 + 2],
+            pixel[i * 4 + 3],
+        ])
+    };
+    let srgb = |color: Vec4| {
+        Vec4::new(
+            srgb_to_linear(color.x),
+            srgb_to_linear(color.y),
+            srgb_to_linear(color.z),
+            color.w,
+        )
+    };
+
+    Some(match format {
+        TextureFormat::R8Unorm => Vec4::new(unorm8(0), unorm8(0), unorm8(0), 1.0),
+        TextureFormat::Rg8Unorm => Vec4::new(unorm8(0), unorm8(0), unorm8(0), unorm8(1)),
+        TextureFormat::Rgba8Unorm => Vec4::new(unorm8(0), unorm8(1), unorm8(2), unorm8(3)),
+        TextureFormat::Rgba8UnormSrgb => {
+            srgb(Vec4::new(unorm8(0), unorm8(1), unorm8(2), unorm8(3)))
+        }
+        TextureFormat::Bgra8Unorm => Vec4::new(unorm8(2), unorm8(1), unorm8(0), unorm8(3)),
+        TextureFormat::Bgra8UnormSrgb => {
+            srgb(Vec4::new(unorm8(2), unorm8(1), unorm8(0), unorm8(3)))
+        }
+        // The image loader turns 16 bit images into uint formats.
+        TextureFormat::R16Unorm | TextureFormat::R16Uint => {
+            Vec4::new(unorm16(0), unorm16(0), unorm16(0), 1.0)
+        }
+        TextureFormat::Rg16Unorm | TextureFormat::Rg16Uint => {
+            Vec4::new(unorm16(0), unorm16(0), unorm16(0), unorm16(1))
+        }
+        TextureFormat::Rgba16Unorm | TextureFormat::Rgba16Uint => {
+            Vec4::new(unorm16(0), unorm16(1), unorm16(2), unorm16(3))
+        }
+        TextureFormat::Rgba16Float => Vec4::new(float16(0), float16(1), float16(2), float16(3)),
+        TextureFormat::R32Float => Vec4::new(float32(0), float32(0), float32(0), 1.0),
+        TextureFormat::Rgba32Float => Vec4::new(float32(0), float32(1), float32(2), float32(3)),
+        _ => return None,
+    })
+}
+
+fn encode(format: TextureFormat, color: Vec4, dest: &mut Vec<u8>) {
+    let unorm8 = |value: f32| (value.clamp(0.0, 1.0) * 255.0).round() as u8;
+
+    match format {
+        TextureFormat::Rgba8Unorm => {
+            dest.extend([
+                unorm8(color.x),
+                unorm8(color.y),
+                unorm8(color.z),
+                unorm8(color.w),
+            ]);
+        }
+        TextureFormat::Rgba8UnormSrgb => {
+            dest.extend([
+                unorm8(linear_to_srgb(color.x)),
+                unorm8(linear_to_srgb(color.y)),
+                unorm8(linear_to_srgb(color.z)),
+                unorm8(color.w),
+            ]);
+        }
+        TextureFormat::Rgba16Float => {
+            for value in color.to_array() {
+                dest.extend(f32_to_f16(value).to_le_bytes());
+            }
+        }
+        _ => unreachable!("Can't convert images to {:?}", format),
+    }
+}
+
+pub(crate) fn srgb_to_linear(value: f32) -> f32 {
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+pub(crate) fn linear_to_srgb(value: f32) -> f32 {
+    if value <= 0.0031308 {
+        value * 12.92
+    } else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+pub(crate) fn f16_to_f32(half: u16) -> f32 {
+    let sign = if half & 0x8000 != 0 { -1.0 } else { 1.0 };
+    let exponent = ((half >> 10) & 0x1f) as i32;
+    let mantissa = (half & 0x3ff) as f32;
+
+    sign * match exponent {
+        0 => mantissa * 2f32.powi(-24),
+        0x1f if mantissa == 0.0 => f32::INFINITY,
+        0x1f => f32::NAN,
+        _ => (1.0 + mantissa / 1024.0) * 2f32.powi(exponent - 15),
+    }
+}
+
+pub(crate) fn f32_to_f16(value: f32) -> u16 {
+    let bits = value.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    if value.is_nan() {
+        return sign | 0x7e00;
+    }
+
+    let value = value.abs();
+    if value >= 65520.0 {
+        // Too big, including infinity.
+        sign | 0x7c00
+    } else if value < 2f32.powi(-14) {
+        // Subnormal or zero.
+        sign | (value * 2f32.powi(24)).round() as u16
+    } else {
+        let exponent = value.log2().floor() as i32;
+        let mantissa = (value / 2f32.powi(exponent) - 1.0) * 1024.0;
+        let mut half = (((exponent + 15) as u16) << 10) + mantissa.round() as u16;
+        // Rounding the mantissa up carries into the exponent, which is only wrong if it makes
+        // the value infinite.
+        if half & 0x7c00 == 0x7c00 {
+            half = 0x7bff;
+        }
+        sign | half
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn f16_round_trips() {
+        for half in (0..=u16::MAX).filter(|half| half & 0x7c00 != 0x7c00) {
+            assert_eq!(f32_to_f16(f16_to_f32(half)), half, "{:#06x}", half);
+        }
+    }
+
+    #[test]
+    fn f16_special_values() {
+        assert_eq!(f16_to_f32(0x3c00), 1.0);
+        assert_eq!(f16_to_f32(0xc000), -2.0);
+        assert_eq!(f16_to_f32(0x0001), 2f32.powi(-24));
+        assert_eq!(f16_to_f32(0x7c00), f32::INFINITY);
+        assert!(f16_to_f32(0x7e00).is_nan());
+
+        assert_eq!(f32_to_f16(65504.0), 0x7bff);
+        assert_eq!(f32_to_f16(1e6), 0x7c00);
+        assert_eq!(f32_to_f16(-f32::INFINITY), 0xfc00);
+        assert_eq!(f32_to_f16(f32::NAN) & 0x7e00, 0x7e00);
+        // Rounds to the closest value, carrying in to the exponent.
+        assert_eq!(f32_to_f16(1.0 + 1.0 / 4096.0), 0x3c00);
+        assert_eq!(f32_to_f16(2.0 - 1.0 / 4096.0), 0x4000);
+    }
+
+    #[test]
+    fn srgb_round_trips() {
+        for value in 0..=255u8 {
+            let srgb = value as f32 / 255.0;
+            let round_trip = linear_to_srgb(srgb_to_linear(srgb));
+            assert!((round_trip - srgb).abs() < 1e-5, "{}", value);
+        }
+        assert!((srgb_to_linear(188.0 / 255.0) - 0.5).abs() < 0.005);
+    }
+
+    #[test]
+    fn converts_between_formats() {
+        let mut dest = Vec::new();
+        convert(
+            &[128, 64],
+            TextureFormat::Rg8Unorm,
+            TextureFormat::Rgba8Unorm,
+            &mut dest,
+        );
+        convert(
+            &[1, 2, 3, 4],
+            TextureFormat::Bgra8Unorm,
+            TextureFormat::Rgba8Unorm,
+            &mut dest,
+        );
+        convert(
+            &[0xff, 0xff],
+            TextureFormat::R16Uint,
+            TextureFormat::Rgba8Unorm,
+            &mut dest,
+        );
+        assert_eq!(dest, [128, 128, 128, 64, 3, 2, 1, 4, 255, 255, 255, 255]);
+
+        let mut dest = Vec::new();
+        convert(
+            &[188, 0, 255, 51],
+            TextureFormat::Rgba8UnormSrgb,
+            TextureFormat::Rgba16Float,
+            &mut dest,
+        );
+        let values: Vec<f32> = dest
+            .chunks_exact(2)
+            .map(|half| f16_to_f32(u16::from_le_bytes([half[0], half[1]])))
+            .collect();
+        assert!((values[0] - 0.5).abs() < 0.005);
+        assert_eq!(&values[1..], &[0.0, 1.0, f16_to_f32(f32_to_f16(0.2))]);
+
+        let mut dest = Vec::new();
+        convert(
+            &[188, 0, 255, 51],
+            TextureFormat::Rgba8UnormSrgb,
+            TextureFormat::Rgba8UnormSrgb,
+            &mut dest,
+        );
+        assert_eq!(dest, [188, 0, 255, 51]);
+    }
+
+    #[test]
+    fn alpha_of_every_channel_count() {
+        assert_eq!(alpha(TextureFormat::R8Unorm, &[10]), 1.0);
+        assert_eq!(alpha(TextureFormat::Rg8Unorm, &[10, 51]), 0.2);
+        assert_eq!(alpha(TextureFormat::Bgra8UnormSrgb, &[1, 2, 3, 51]), 0.2);
+        assert_eq!(alpha(TextureFormat::Rg11b10Float, &[0; 4]), 1.0);
+    }
+
+    #[test]
+    fn supported_formats() {
+        assert!(can_decode(TextureFormat::Rgba16Uint));
+        assert!(!can_decode(TextureFormat::Bc1RgbaUnorm));
+        assert!(can_sample(TextureFormat::Bc7RgbaUnormSrgb));
+        assert!(!can_sample(TextureFormat::R16Uint));
+        assert!(can_sample(TextureFormat::Rg11b10Float));
+        assert!(!can_sample(TextureFormat::R8Unorm));
+        assert!(!can_sample(TextureFormat::Rg8Unorm));
+        assert!(can_encode(TextureFormat::Rgba16Float));
+        assert!(!can_encode(TextureFormat::Bgra8Unorm));
+    }
+}
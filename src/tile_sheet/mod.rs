@@ -0,0 +1,846 @@
+pub(crate) mod convert;
+
+use std::fmt;
+
+use bevy::{
+    math::const_uvec2,
+    prelude::*,
+    reflect::TypeUuid,
+    render::{
+        render_resource::TextureFormat,
+        texture::{BevyDefault, TextureFormatPixelInfo},
+    },
+    utils::{HashMap, HashSet},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TileAnimationMode {
+    Loop,
+    /// Plays forward and then backward.
+    PingPong,
+    /// Plays once and then stays on the last frame.
+    Once,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TileAnimationFrame {
+    pub idx: u16,
+    /// In seconds.
+    pub duration: f32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TileAnimation {
+    pub frames: Vec<TileAnimationFrame>,
+    pub mode: TileAnimationMode,
+}
+
+impl TileAnimation {
+    pub fn new(frames: Vec<TileAnimationFrame>, mode: TileAnimationMode) -> Self {
+        assert!(
+            !frames.is_empty(),
+            "A tile animation needs at least one frame"
+        );
+
+        TileAnimation { frames, mode }
+    }
+
+    /// Every frame is shown for `frame_duration` seconds.
+    pub fn from_indices(
+        indices: impl IntoIterator<Item = u16>,
+        frame_duration: f32,
+        mode: TileAnimationMode,
+    ) -> Self {
+        Self::new(
+            indices
+                .into_iter()
+                .map(|idx| TileAnimationFrame {
+                    idx,
+                    duration: frame_duration,
+                })
+                .collect(),
+            mode,
+        )
+    }
+
+    pub fn duration(&self) -> f32 {
+        self.frames.iter().map(|frame| frame.duration).sum()
+    }
+
+    /// The sprite index shown `time` seconds after startup. Matches what `chunk.wgsl` does.
+    pub fn frame_at(&self, time: f32) -> u16 {
+        let duration = self.duration();
+        // Animations without any length show their first frame.
+        if duration <= 0.0 {
+            return self.frames.first().map_or(0, |frame| frame.idx);
+        }
+
+        let time = match self.mode {
+            TileAnimationMode::Loop => time.rem_euclid(duration),
+            TileAnimationMode::PingPong => {
+                let time = time.rem_euclid(duration * 2.0);
+                if time > duration {
+                    duration * 2.0 - time
+                } else {
+                    time
+                }
+            }
+            TileAnimationMode::Once => time.min(duration),
+        };
+
+        let mut end = 0.0;
+        for frame in &self.frames {
+            end += frame.duration;
+            if time < end {
+                return frame.idx;
+            }
+        }
+        self.frames.last().unwrap().idx
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TileFilter {
+    /// Uses the closest pixel, which keeps pixel art sharp.
+    Nearest,
+    /// Blends the closest pixels.
+    Linear,
+}
+
+/// How the sprites of a `TileSheet` are filtered when they are drawn bigger or smaller than
+/// their size in pixels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TileSheetSampling {
+    pub filter: TileFilter,
+    /// Generates smaller versions of every sprite, so zoomed out maps don't shimmer. Only for
+    /// uncompressed 8 bit unorm, 16 bit unorm or float and 32 bit float formats.
+    pub mipmaps: bool,
+    /// The maximum number of samples for anisotropic filtering, rounded down to 1, 2, 4, 8 or 16.
+    /// Only used with linear filtering and on GPUs that support it.
+    pub anisotropy: u8,
+}
+
+impl TileSheetSampling {
+    /// Nearest filtering without mipmaps, the default.
+    pub const PIXEL_ART: TileSheetSampling = TileSheetSampling {
+        filter: TileFilter::Nearest,
+        mipmaps: false,
+        anisotropy: 1,
+    };
+
+    /// Linear filtering with mipmaps and 16x anisotropic filtering, for high resolution sprites.
+    pub const SMOOTH: TileSheetSampling = TileSheetSampling {
+        filter: TileFilter::Linear,
+        mipmaps: true,
+        anisotropy: 16,
+    };
+}
+
+impl Default for TileSheetSampling {
+    fn default() -> Self {
+        Self::PIXEL_ART
+    }
+}
+
+/// Where the tiles are in a tile set image, in pixels. Tiles are read row by row from the top
+/// left, partial tiles at the right and bottom are skipped. Images with several layers, like
+/// prebuilt array textures, are read layer by layer with the same layout, so an array with one
+/// tile per layer gives one sprite per layer. Only the first mip level of an image is used, and
+/// images with several layers can't have mipmaps.
+///
+/// For block compressed images the tile size and the layout have to be multiples of the block
+/// size.
+///
+/// There is no edge extrusion, because none is needed: every tile is copied in to its own layer
+/// of a texture array, which is sampled with clamp to edge and gets its own mipmaps. Linear
+/// filtering and mipmaps never read the neighbouring tiles of the source image, even with a
+/// `spacing` of 0.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct TileSetLayout {
+    /// Skipped around the tiles on every side.
+    pub margin: UVec2,
+    /// Skipped between neighbouring tiles.
+    pub spacing: UVec2,
+    /// Skipped at the top left, before the margin.
+    pub offset: UVec2,
+}
+
+impl TileSetLayout {
+    /// The number of columns and rows of tiles in an image of `image_size`.
+    pub fn grid_size(&self, image_size: UVec2, tile_size: UVec2) -> UVec2 {
+        let skipped = self.offset + self.margin * 2;
+        let available = image_size.max(skipped) - skipped;
+        (available + self.spacing) / (tile_size + self.spacing)
+    }
+
+    /// Whether the tiles start and end on the edges of blocks of `block` pixels.
+    fn is_aligned(&self, tile_size: UVec2, block: UVec2) -> bool {
+        [tile_size, self.margin, self.spacing, self.offset]
+            .iter()
+            .all(|size| (*size % block).cmpeq(UVec2::ZERO).all())
+    }
+
+    /// The top left pixel of the tile in `column` and `row`.
+    fn tile_origin(&self, column: u32, row: u32, tile_size: UVec2) -> UVec2 {
+        self.offset + self.margin + UVec2::new(column, row) * (tile_size + self.spacing)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TileSheetError {
+    /// The image has a format that can't be converted to the format of the tile sheet. Its
+    /// sprites are left blank.
+    UnsupportedFormat {
+        image: Handle<Image>,
+        format: TextureFormat,
+    },
+    /// The image is block compressed, and the tile size or its `TileSetLayout` aren't multiples
+    /// of the block size. Its sprites are left blank.
+    UnalignedBlocks {
+        image: Handle<Image>,
+        format: TextureFormat,
+    },
+    /// The image has several layers and mipmaps. Loaders store those either layer by layer or
+    /// level by level, so its sprites are left blank instead of guessing.
+    LayeredMipmaps { image: Handle<Image> },
+}
+
+impl fmt::Display for TileSheetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TileSheetError::UnsupportedFormat { image, format } => write!(
+                f,
+                "Can't convert tile set {:?} from {:?}, its sprites are left blank",
+                image, format
+            ),
+            TileSheetError::UnalignedBlocks { image, format } => write!(
+                f,
+                "The tiles of tile set {:?} don't line up with the blocks of {:?}, its sprites are \
+                 left blank",
+                image, format
+            ),
+            TileSheetError::LayeredMipmaps { image } => write!(
+                f,
+                "Tile set {:?} has several layers and mipmaps, its sprites are left blank",
+                image
+            ),
+        }
+    }
+}
+
+impl std::error::Error for TileSheetError {}
+
+#[derive(Debug, Clone, TypeUuid)]
+#[uuid = "fd3a76be-60a3-4b67-a2da-8c987f65ae16"]
+pub struct TileSheet {
+    tile_sets: Vec<Handle<Image>>,
+    pub(crate) tile_size: UVec2,
+    pub(crate) tile_data: Vec<u8>,
+    pub(crate) array_count: u32,
+    pub(crate) format: Option<TextureFormat>,
+    // The format the images get converted to, if they don't all have the same format already.
+    target_format: Option<TextureFormat>,
+    pub(crate) animations: Vec<TileAnimation>,
+    pub(crate) sampling: TileSheetSampling,
+    layouts: HashMap<Handle<Image>, TileSetLayout>,
+    // Set when a layout changed, so the tiles get rebuilt without an image changing.
+    layouts_changed: bool,
+}
+
+impl TileSheet {
+    pub fn new(mut tile_sets: Vec<Handle<Image>>, tile_size: UVec2) -> Self {
+        tile_sets.sort();
+        tile_sets.dedup();
+
+        TileSheet {
+            tile_sets,
+            tile_size,
+            tile_data: Vec::new(),
+            array_count: 0,
+            format: None,
+            target_format: None,
+            animations: Vec::new(),
+            sampling: TileSheetSampling::default(),
+            layouts: HashMap::default(),
+            layouts_changed: false,
+        }
+    }
+
+    pub fn empty() -> TileSheet {
+        TileSheet {
+            tile_sets: Vec::new(),
+            tile_size: const_uvec2!([1, 1]),
+            tile_data: vec![0, 0, 0, 0],
+            array_count: 1,
+            format: Some(TextureFormat::bevy_default()),
+            target_format: None,
+            animations: Vec::new(),
+            sampling: TileSheetSampling::default(),
+            layouts: HashMap::default(),
+            layouts_changed: false,
+        }
+    }
+
+    /// Converts every image to `format`, one of `Rgba8UnormSrgb`, `Rgba8Unorm` or
+    /// `Rgba16Float`. Without it images are only converted, to `Rgba8UnormSrgb`, if they don't
+    /// all have the same format or their format can't be filtered or has less than three
+    /// channels, like the 16 bit uint and the grayscale formats the image loader creates.
+    pub fn with_format(mut self, format: TextureFormat) -> Self {
+        assert!(
+            convert::can_encode(format),
+            "Can't convert tile sets to {:?}",
+            format
+        );
+
+        self.target_format = Some(format);
+        self.layouts_changed = true;
+        self
+    }
+
+    pub fn with_sampling(mut self, sampling: TileSheetSampling) -> Self {
+        self.sampling = sampling;
+        self
+    }
+
+    #[inline]
+    pub fn sampling(&self) -> TileSheetSampling {
+        self.sampling
+    }
+
+    #[inline]
+    pub fn set_sampling(&mut self, sampling: TileSheetSampling) {
+        self.sampling = sampling;
+    }
+
+    /// Sets the layout of the tile set `image`, which has to be one of the tile sets of the sheet.
+    pub fn with_layout(mut self, image: &Handle<Image>, layout: TileSetLayout) -> Self {
+        self.set_layout(image, layout);
+        self
+    }
+
+    /// The layout of the tile set `image`, a gapless grid by default.
+    #[inline]
+    pub fn layout(&self, image: &Handle<Image>) -> TileSetLayout {
+        self.layouts.get(image).copied().unwrap_or_default()
+    }
+
+    pub fn set_layout(&mut self, image: &Handle<Image>, layout: TileSetLayout) {
+        assert!(
+            self.tile_sets.contains(image),
+            "The image isn't a tile set of this tile sheet"
+        );
+
+        self.layouts.insert(image.clone_weak(), layout);
+        self.layouts_changed = true;
+    }
+
+    /// Returns the index to use in `TileKind::AnimatedSprite`. Panics if the tile sheet already
+    /// has `u16::MAX + 1` animations, the most an index can address.
+    pub fn add_animation(&mut self, animation: TileAnimation) -> u16 {
+        let idx = u16::try_from(self.animations.len()).unwrap_or_else(|_| {
+            panic!(
+                "A tile sheet can't have more than {} animations",
+                u16::MAX as usize + 1
+            )
+        });
+        self.animations.push(animation);
+        idx
+    }
+
+    #[inline]
+    pub fn animation(&self, animation: u16) -> Option<&TileAnimation> {
+        self.animations.get(animation as usize)
+    }
+
+    #[inline]
+    pub fn animations(&self) -> &[TileAnimation] {
+        &self.animations
+    }
+
+    /// The alpha of sprite `idx` at `uv`, with (0, 0) at the bottom left corner, using the closest
+    /// pixel. Formats without an alpha channel are opaque. `None` if the sprite isn't loaded.
+    pub fn alpha_at(&self, idx: u16, uv: Vec2) -> Option<f32> {
+        let format = self.format?;
+        if idx as u32 >= self.array_count {
+            return None;
+        }
+        // Block compressed sprites count as opaque.
+        if format.describe().block_dimensions != (1, 1) {
+            return Some(1.0);
+        }
+
+        let pixel_size = format.pixel_size();
+        let texel = (uv * self.tile_size.as_vec2())
+            .floor()
+            .clamp(Vec2::ZERO, (self.tile_size - UVec2::ONE).as_vec2())
+            .as_uvec2();
+        // The rows of the sprites are stored from the top.
+        let row = self.tile_size.y - 1 - texel.y;
+
+        let start = ((idx as usize * self.tile_size.y as usize + row as usize)
+            * self.tile_size.x as usize
+            + texel.x as usize)
+            * pixel_size;
+        let pixel = self.tile_data.get(start..start + pixel_size)?;
+
+        Some(convert::alpha(format, pixel))
+    }
+
+    /// The format the tiles are stored in. The images are only used as they are if they all have
+    /// the same format and it can be sampled, otherwise they are converted.
+    fn tile_format(
+        target_format: Option<TextureFormat>,
+        image_formats: &[TextureFormat],
+    ) -> Option<TextureFormat> {
+        let first_format = image_formats.first().copied();
+        let same_format = image_formats
+            .iter()
+            .all(|format| Some(*format) == first_format);
+        match (target_format, first_format) {
+            (Some(target_format), _) => Some(target_format),
+            (None, Some(first_format)) if same_format && convert::can_sample(first_format) => {
+                Some(first_format)
+            }
+            (None, Some(_)) => Some(TextureFormat::Rgba8UnormSrgb),
+            (None, None) => None,
+        }
+    }
+
+    /// Whether `update_images` would rebuild the tiles.
+    pub(crate) fn needs_update(&self, updated_images: &HashSet<Handle<Image>>) -> bool {
+        self.layouts_changed
+            || self
+                .tile_sets
+                .iter()
+                .any(|handle| updated_images.contains(handle))
+    }
+
+    /// Rebuilds the sprites from the tile set images if one of them is in `updated_images`.
+    /// Images that can't be used get blank sprites, so the indices of the other sprites don't
+    /// change, and the first of them is returned as an error.
+    pub fn update_images(
+        &mut self,
+        images: &Assets<Image>,
+        updated_images: &HashSet<Handle<Image>>,
+    ) -> Result<(), TileSheetError> {
+        if !self.needs_update(updated_images) {
+            return Ok(());
+        }
+        self.layouts_changed = false;
+
+        let loaded: Vec<(&Handle<Image>, &Image)> = self
+            .tile_sets
+            .iter()
+            .filter_map(|handle| Some((handle, images.get(handle)?)))
+            .collect();
+
+        let formats: Vec<TextureFormat> = loaded
+            .iter()
+            .map(|(_, image)| image.texture_descriptor.format)
+            .collect();
+        let format = Self::tile_format(self.target_format, &formats);
+
+        let mut tile_data = Vec::with_capacity(self.tile_data.len());
+        let mut result = Ok(());
+        if let Some(format) = format {
+            for (handle, image) in loaded {
+                let tiles = Self::make_into_tiles(
+                    &mut tile_data,
+                    handle,
+                    image,
+                    format,
+                    self.tile_size,
+                    self.layout(handle),
+                );
+                if result.is_ok() {
+                    result = tiles;
+                }
+            }
+        }
+
+        self.tile_data = tile_data;
+        self.format = format;
+        if let Some(format) = self.format {
+            self.array_count = (self.tile_data.len() / layer_bytes(format, self.tile_size)) as u32;
+        }
+        result
+    }
+
+    /// Appends the tiles of every layer of `image` in `format` to `dest`. Block compressed
+    /// images are sliced by blocks, so the tiles and the layout have to line up with them.
+    fn make_into_tiles(
+        dest: &mut Vec<u8>,
+        handle: &Handle<Image>,
+        image: &Image,
+        format: TextureFormat,
+        tile_size: UVec2,
+        layout: TileSetLayout,
+    ) -> Result<(), TileSheetError> {
+        let image_format = image.texture_descriptor.format;
+        let image_size = UVec2::new(
+            image.texture_descriptor.size.width,
+            image.texture_descriptor.size.height,
+        );
+        let layers = image.texture_descriptor.size.depth_or_array_layers.max(1) as usize;
+        let grid_size = layout.grid_size(image_size, tile_size);
+
+        let info = image_format.describe();
+        let block = UVec2::new(
+            info.block_dimensions.0 as u32,
+            info.block_dimensions.1 as u32,
+        );
+        let error = if image_format != format && !convert::can_decode(image_format) {
+            Some(TileSheetError::UnsupportedFormat {
+                image: handle.clone_weak(),
+                format: image_format,
+            })
+        } else if !layout.is_aligned(tile_size, block) {
+            Some(TileSheetError::UnalignedBlocks {
+                image: handle.clone_weak(),
+                format: image_format,
+            })
+        } else if layers > 1 && image.texture_descriptor.mip_level_count > 1 {
+            Some(TileSheetError::LayeredMipmaps {
+                image: handle.clone_weak(),
+            })
+        } else {
+            None
+        };
+        if let Some(error) = error {
+            let tile_count = (grid_size.x * grid_size.y) as usize * layers;
+            dest.resize(dest.len() + tile_count * layer_bytes(format, tile_size), 0);
+            return Err(error);
+        }
+
+        let block_size = info.block_size as usize;
+        let image_row_bytes = ((image_size.x + block.x - 1) / block.x) as usize * block_size;
+        let tile_row_bytes = (tile_size.x / block.x) as usize * block_size;
+
+        // Only the first mip level is used. It comes first in images with a single layer, and
+        // images with several layers have no other levels.
+        for layer in 0..layers {
+            let layer_start = layer * layer_bytes(image_format, image_size);
+            for row in 0..grid_size.y {
+                for column in 0..grid_size.x {
+                    let origin = layout.tile_origin(column, row, tile_size) / block;
+                    for y in 0..tile_size.y / block.y {
+                        let start = layer_start
+                            + (origin.y + y) as usize * image_row_bytes
+                            + origin.x as usize * block_size;
+                        let src = &image.data[start..start + tile_row_bytes];
+                        if image_format == format {
+                            dest.extend_from_slice(src);
+                        } else {
+                            convert::convert(src, image_format, format, dest);
+                        }
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The bytes of one layer of `size` pixels in `format`, rounded up to whole blocks.
+pub(crate) fn layer_bytes(format: TextureFormat, size: UVec2) -> usize {
+    let info = format.describe();
+    let blocks = UVec2::new(
+        (size.x + info.block_dimensions.0 as u32 - 1) / info.block_dimensions.0 as u32,
+        (size.y + info.block_dimensions.1 as u32 - 1) / info.block_dimensions.1 as u32,
+    );
+    (blocks.x * blocks.y) as usize * info.block_size as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::render::render_resource::Extent3d;
+
+    use super::*;
+
+    fn frames(durations: &[f32]) -> Vec<TileAnimationFrame> {
+        durations
+            .iter()
+            .enumerate()
+            .map(|(idx, duration)| TileAnimationFrame {
+                idx: idx as u16 + 10,
+                duration: *duration,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn frame_at_loop() {
+        let animation = TileAnimation::new(frames(&[1.0, 2.0]), TileAnimationMode::Loop);
+
+        assert_eq!(animation.frame_at(0.0), 10);
+        assert_eq!(animation.frame_at(0.5), 10);
+        assert_eq!(animation.frame_at(1.0), 11);
+        assert_eq!(animation.frame_at(2.9), 11);
+        assert_eq!(animation.frame_at(3.5), 10);
+        assert_eq!(animation.frame_at(301.5), 11);
+    }
+
+    #[test]
+    fn frame_at_ping_pong() {
+        let animation = TileAnimation::from_indices([10, 11, 12], 1.0, TileAnimationMode::PingPong);
+
+        assert_eq!(animation.frame_at(0.5), 10);
+        assert_eq!(animation.frame_at(2.5), 12);
+        assert_eq!(animation.frame_at(3.5), 12);
+        assert_eq!(animation.frame_at(4.5), 11);
+        assert_eq!(animation.frame_at(5.5), 10);
+        assert_eq!(animation.frame_at(6.5), 10);
+    }
+
+    #[test]
+    fn frame_at_once_stays_on_the_last_frame() {
+        let animation = TileAnimation::from_indices([10, 11], 0.5, TileAnimationMode::Once);
+
+        assert_eq!(animation.frame_at(0.25), 10);
+        assert_eq!(animation.frame_at(0.75), 11);
+        assert_eq!(animation.frame_at(100.0), 11);
+    }
+
+    #[test]
+    fn frame_at_without_length_shows_the_first_frame() {
+        for mode in [
+            TileAnimationMode::Loop,
+            TileAnimationMode::PingPong,
+            TileAnimationMode::Once,
+        ] {
+            let animation = TileAnimation::new(frames(&[0.0, 0.0]), mode);
+            assert_eq!(animation.frame_at(1.5), 10);
+
+            let animation = TileAnimation {
+                frames: Vec::new(),
+                mode,
+            };
+            assert_eq!(animation.frame_at(1.5), 0);
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn animations_need_frames() {
+        TileAnimation::new(Vec::new(), TileAnimationMode::Loop);
+    }
+
+    #[test]
+    fn add_animation_returns_consecutive_indices() {
+        let mut tile_sheet = TileSheet::empty();
+        let animation = TileAnimation::from_indices([0], 1.0, TileAnimationMode::Loop);
+
+        assert_eq!(tile_sheet.add_animation(animation.clone()), 0);
+        assert_eq!(tile_sheet.add_animation(animation.clone()), 1);
+
+        tile_sheet.animations = vec![animation.clone(); u16::MAX as usize];
+        assert_eq!(tile_sheet.add_animation(animation), u16::MAX);
+    }
+
+    #[test]
+    #[should_panic(expected = "more than 65536 animations")]
+    fn animation_indices_dont_wrap() {
+        let mut tile_sheet = TileSheet::empty();
+        let animation = TileAnimation::from_indices([0], 1.0, TileAnimationMode::Loop);
+
+        tile_sheet.animations = vec![animation.clone(); u16::MAX as usize + 1];
+        tile_sheet.add_animation(animation);
+    }
+
+    fn image(size: UVec2, layers: u32, format: TextureFormat, data: Vec<u8>) -> Image {
+        let mut image = Image::default();
+        image.texture_descriptor.size = Extent3d {
+            width: size.x,
+            height: size.y,
+            depth_or_array_layers: layers,
+        };
+        image.texture_descriptor.format = format;
+        image.data = data;
+        image
+    }
+
+    /// An R8 image where every pixel is its `x + 10 * y`, with y = 0 at the top.
+    fn numbered_image(size: UVec2) -> Image {
+        let data = (0..size.y)
+            .flat_map(|y| (0..size.x).map(move |x| (x + 10 * y) as u8))
+            .collect();
+        image(size, 1, TextureFormat::R8Unorm, data)
+    }
+
+    fn tiles(image: &Image, tile_size: UVec2, layout: TileSetLayout) -> Vec<u8> {
+        let mut tiles = Vec::new();
+        TileSheet::make_into_tiles(
+            &mut tiles,
+            &Handle::default(),
+            image,
+            image.texture_descriptor.format,
+            tile_size,
+            layout,
+        )
+        .unwrap();
+        tiles
+    }
+
+    fn tile_errors(
+        image: &Image,
+        format: TextureFormat,
+        tile_size: UVec2,
+    ) -> (Vec<u8>, TileSheetError) {
+        let mut tiles = Vec::new();
+        let error = TileSheet::make_into_tiles(
+            &mut tiles,
+            &Handle::default(),
+            image,
+            format,
+            tile_size,
+            TileSetLayout::default(),
+        )
+        .unwrap_err();
+        (tiles, error)
+    }
+
+    #[test]
+    fn grayscale_images_are_converted_on_their_own_too() {
+        use TextureFormat::*;
+
+        assert_eq!(
+            TileSheet::tile_format(None, &[R8Unorm]),
+            Some(Rgba8UnormSrgb)
+        );
+        assert_eq!(
+            TileSheet::tile_format(None, &[Rg8Unorm, Rg8Unorm]),
+            Some(Rgba8UnormSrgb)
+        );
+        assert_eq!(
+            TileSheet::tile_format(None, &[R8Unorm, Rgba8Unorm]),
+            Some(Rgba8UnormSrgb)
+        );
+        assert_eq!(
+            TileSheet::tile_format(None, &[Rgba8Unorm, Rgba8Unorm]),
+            Some(Rgba8Unorm)
+        );
+        assert_eq!(
+            TileSheet::tile_format(Some(Rgba16Float), &[R8Unorm]),
+            Some(Rgba16Float)
+        );
+        assert_eq!(TileSheet::tile_format(None, &[]), None);
+    }
+
+    #[test]
+    fn grid_size_skips_the_layout_and_partial_tiles() {
+        let layout = TileSetLayout {
+            margin: UVec2::new(1, 2),
+            spacing: UVec2::new(2, 1),
+            offset: UVec2::new(3, 0),
+        };
+
+        assert_eq!(
+            layout.grid_size(UVec2::new(20, 14), UVec2::new(4, 4)),
+            UVec2::new(2, 2)
+        );
+        assert_eq!(
+            layout.grid_size(UVec2::new(4, 4), UVec2::new(4, 4)),
+            UVec2::ZERO
+        );
+    }
+
+    #[test]
+    fn tiles_only_hold_their_own_pixels() {
+        let image = numbered_image(UVec2::new(4, 2));
+        let tiles = tiles(&image, UVec2::new(2, 2), TileSetLayout::default());
+
+        assert_eq!(tiles, vec![0, 1, 10, 11, 2, 3, 12, 13]);
+    }
+
+    #[test]
+    fn tiles_skip_margin_spacing_and_offset() {
+        let image = numbered_image(UVec2::new(8, 5));
+        let layout = TileSetLayout {
+            margin: UVec2::new(1, 1),
+            spacing: UVec2::new(1, 0),
+            offset: UVec2::new(1, 0),
+        };
+        let tiles = tiles(&image, UVec2::new(2, 3), layout);
+
+        assert_eq!(tiles, vec![12, 13, 22, 23, 32, 33, 15, 16, 25, 26, 35, 36]);
+    }
+
+    #[test]
+    fn compressed_tiles_are_sliced_by_blocks() {
+        // An 8x8 BC1 image is 2x2 blocks of 8 bytes.
+        let data: Vec<u8> = (0..4).flat_map(|block| [block; 8]).collect();
+        let image = image(UVec2::new(8, 8), 1, TextureFormat::Bc1RgbaUnorm, data);
+
+        let small_tiles = tiles(&image, UVec2::new(4, 4), TileSetLayout::default());
+        assert_eq!(small_tiles, image.data);
+
+        let wide_tiles = tiles(&image, UVec2::new(8, 4), TileSetLayout::default());
+        assert_eq!(wide_tiles, image.data);
+
+        let layout = TileSetLayout {
+            offset: UVec2::new(4, 0),
+            ..Default::default()
+        };
+        let offset_tiles = tiles(&image, UVec2::new(4, 4), layout);
+        assert_eq!(offset_tiles, [[1; 8], [3; 8]].concat());
+    }
+
+    #[test]
+    fn unaligned_compressed_tiles_are_blank() {
+        let image = image(
+            UVec2::new(8, 8),
+            1,
+            TextureFormat::Bc1RgbaUnorm,
+            vec![1; 32],
+        );
+        let (tiles, error) = tile_errors(&image, TextureFormat::Bc1RgbaUnorm, UVec2::new(2, 2));
+
+        assert!(matches!(error, TileSheetError::UnalignedBlocks { .. }));
+        // 16 tiles of one block each.
+        assert_eq!(tiles, vec![0; 16 * 8]);
+    }
+
+    #[test]
+    fn compressed_tiles_cant_be_converted() {
+        let image = image(UVec2::new(4, 4), 1, TextureFormat::Bc1RgbaUnorm, vec![1; 8]);
+        let (tiles, error) = tile_errors(&image, TextureFormat::Rgba8UnormSrgb, UVec2::new(4, 4));
+
+        assert!(matches!(error, TileSheetError::UnsupportedFormat { .. }));
+        assert_eq!(tiles, vec![0; 4 * 4 * 4]);
+    }
+
+    #[test]
+    fn every_layer_of_an_array_is_sliced() {
+        let data: Vec<u8> = (0..2).flat_map(|layer| [layer; 2 * 2]).collect();
+        let image = image(UVec2::new(2, 2), 2, TextureFormat::R8Unorm, data);
+
+        let tiles = tiles(&image, UVec2::new(2, 2), TileSetLayout::default());
+        assert_eq!(tiles, vec![0, 0, 0, 0, 1, 1, 1, 1]);
+    }
+
+    #[test]
+    fn only_the_first_mip_level_is_used() {
+        let mut image = image(
+            UVec2::new(2, 2),
+            1,
+            TextureFormat::R8Unorm,
+            vec![1, 2, 3, 4, 9],
+        );
+        image.texture_descriptor.mip_level_count = 2;
+
+        let tiles = tiles(&image, UVec2::new(2, 2), TileSetLayout::default());
+        assert_eq!(tiles, vec![1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn arrays_with_mipmaps_are_blank() {
+        // Two layers of 2x2 pixels with two mip levels, layer by layer.
+        let data = vec![1, 2, 3, 4, 9, 5, 6, 7, 8, 9];
+        let mut image = image(UVec2::new(2, 2), 2, TextureFormat::R8Unorm, data);
+        image.texture_descriptor.mip_level_count = 2;
+        let (tiles, error) = tile_errors(&image, TextureFormat::R8Unorm, UVec2::new(2, 2));
+
+        assert!(matches!(error, TileSheetError::LayeredMipmaps { .. }));
+        assert_eq!(tiles, vec![0; 2 * 4]);
+    }
+}